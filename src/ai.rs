@@ -0,0 +1,238 @@
+use crate::{Ball, Paddle, Pong, Track, Vec2};
+use crate::command::Commands;
+use crate::config::GameConfig;
+
+const MAX_BOUNCES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard
+}
+
+impl Difficulty {
+    fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None
+        }
+    }
+    // How close to its target the paddle settles
+    fn dead_zone(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.15,
+            Difficulty::Normal => 0.08,
+            Difficulty::Hard => 0.03
+        }
+    }
+    // How near an approaching ball has to be before the paddle follows it
+    fn reaction_distance(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 1.4,
+            Difficulty::Hard => f32::INFINITY
+        }
+    }
+}
+
+pub fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("ai", "ai <left|right|top|bottom|left2|right2> <off|easy|normal|hard>", |pong, args| {
+        let paddle = match args[0] {
+            "left" => &mut pong.left,
+            "right" => &mut pong.right,
+            "top" => &mut pong.square.top,
+            "bottom" => &mut pong.square.bottom,
+            "left2" => &mut pong.doubles.left,
+            "right2" => &mut pong.doubles.right,
+            side => return Err(format!("Unknown side {}", side))
+        };
+        paddle.ai = match args[1] {
+            "off" => None,
+            name => Some(Difficulty::from_name(name)
+                         .ok_or_else(|| format!("Unknown difficulty {}", name))?)
+        };
+        paddle.up = false;
+        paddle.down = false;
+        Ok(format!("{} paddle: {}", args[0], args[1]))
+    });
+}
+
+// Steers a computer controlled paddle by pressing its up and down inputs.
+// Hard opponents aim for where the ball will cross the paddle, others follow
+// the ball once it comes close enough. With several balls they play the one
+// that will reach them first. Positions are taken along and across the
+// paddle's track, so paddles on any side of the field play the same way.
+pub fn control(paddle: &mut Paddle, difficulty: Difficulty, balls: &[Ball], config: &GameConfig) {
+    if let Track::Arc { radius, .. } = paddle.track {
+        return control_arc(paddle, difficulty, balls, radius);
+    }
+    let position = Vec2::new(paddle.across(&paddle.position), paddle.along(&paddle.position));
+    let local = |v: &Vec2| Vec2::new(paddle.across(v), paddle.along(v));
+    let time_to_paddle = |ball: &Ball| (position.x - paddle.across(&ball.position)) / paddle.across(&ball.velocity);
+    let approaching = balls.iter()
+        .filter(|ball| time_to_paddle(ball) > 0.0)
+        .min_by(|a, b| time_to_paddle(a).total_cmp(&time_to_paddle(b)));
+    let target = match approaching {
+        None => 0.0,
+        Some(ball) if difficulty == Difficulty::Hard => {
+            let path = predict_path(&local(&ball.position), &local(&ball.velocity), config.wall, config.goal_line);
+            intercept(&path, position.x).unwrap_or_else(|| paddle.along(&ball.position))
+        },
+        Some(ball) if (paddle.across(&ball.position) - position.x).abs() < difficulty.reaction_distance() => {
+            paddle.along(&ball.position)
+        },
+        Some(_) => position.y
+    };
+
+    // Paddles sharing a side stay in their own zones
+    let target = match paddle.zone {
+        Some((low, high)) => target.max(low).min(high),
+        None => target
+    };
+
+    let dead_zone = difficulty.dead_zone();
+    paddle.up = target > position.y + dead_zone;
+    paddle.down = target < position.y - dead_zone;
+}
+
+// Paddles on a circle play balls heading out through their half of it. Hard
+// opponents aim for where the ball will leave the circle.
+fn control_arc(paddle: &mut Paddle, difficulty: Difficulty, balls: &[Ball], radius: f32) {
+    let position = paddle.along(&paddle.position);
+    let half = paddle.position.x.signum();
+    let approaching = balls.iter()
+        .filter(|ball| ball.velocity.x * half > 0.0)
+        .filter_map(|ball| exit_time(&ball.position, &ball.velocity, radius).map(|time| (ball, time)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    let target = match approaching {
+        None => 0.0,
+        Some((ball, time)) if difficulty == Difficulty::Hard => {
+            paddle.along(&Vec2::new(ball.position.x + ball.velocity.x * time,
+                                    ball.position.y + ball.velocity.y * time))
+        },
+        Some((ball, _)) if radius - ball.position.length() < difficulty.reaction_distance() => {
+            paddle.along(&ball.position)
+        },
+        Some(_) => position
+    };
+
+    let dead_zone = difficulty.dead_zone();
+    paddle.up = target > position + dead_zone;
+    paddle.down = target < position - dead_zone;
+}
+
+// Time until a ball moving in a straight line crosses the circle of `radius`
+// on its way out
+fn exit_time(position: &Vec2, velocity: &Vec2, radius: f32) -> Option<f32> {
+    let a = velocity.x * velocity.x + velocity.y * velocity.y;
+    let b = position.x * velocity.x + position.y * velocity.y;
+    let c = position.x * position.x + position.y * position.y - radius * radius;
+    let discriminant = b * b - a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let time = (-b + discriminant.sqrt()) / a;
+    if time > 0.0 { Some(time) } else { None }
+}
+
+// Follows the ball through wall bounces until it reaches a goal line,
+// ignoring the paddles
+pub fn predict_path(position: &Vec2, velocity: &Vec2, wall: f32, goal_line: f32) -> Vec<Vec2> {
+    let mut points = vec![position.clone()];
+    let mut p = position.clone();
+    let mut v = velocity.clone();
+    let time_to = |from: f32, speed: f32, limit: f32| {
+        if speed == 0.0 { f32::INFINITY } else { (limit * speed.signum() - from) / speed }
+    };
+    for _ in 0..MAX_BOUNCES {
+        let to_goal = time_to(p.x, v.x, goal_line);
+        let to_wall = time_to(p.y, v.y, wall);
+        let t = to_goal.min(to_wall).max(0.0);
+        if !t.is_finite() {
+            break;
+        }
+        p = Vec2::new(p.x + v.x * t, p.y + v.y * t);
+        points.push(p.clone());
+        if to_goal <= to_wall {
+            break;
+        }
+        v.y = -v.y;
+    }
+    points
+}
+
+fn intercept(path: &[Vec2], x: f32) -> Option<f32> {
+    path.windows(2).find_map(|segment| {
+        let (a, b) = (&segment[0], &segment[1]);
+        if (a.x - x) * (b.x - x) <= 0.0 && a.x != b.x {
+            Some(a.y + (b.y - a.y) * (x - a.x) / (b.x - a.x))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PADDLE_SHAPE;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn path_bounces_off_the_walls_until_the_goal_line() {
+        let path = predict_path(&Vec2::zero(), &Vec2::new(1.0, 1.0), 0.5, 1.0);
+        let expected = [(0.0, 0.0), (0.5, 0.5), (1.0, 0.0)];
+        assert_eq!(path.len(), expected.len());
+        assert!(path.iter().zip(expected).all(|(p, (x, y))| close(p.x, x) && close(p.y, y)));
+
+        // Balls heading left end on the left goal line, straight ones at once
+        let path = predict_path(&Vec2::new(0.2, 0.1), &Vec2::new(-1.0, 0.0), 0.5, 1.0);
+        assert_eq!(path.len(), 2);
+        assert!(close(path[1].x, -1.0) && close(path[1].y, 0.1));
+
+        // Steep shots give up after a few bounces
+        let path = predict_path(&Vec2::zero(), &Vec2::new(0.001, 1.0), 0.5, 1.0);
+        assert_eq!(path.len(), MAX_BOUNCES + 1);
+    }
+
+    #[test]
+    fn intercept_finds_where_the_path_crosses_the_paddle() {
+        let path = predict_path(&Vec2::zero(), &Vec2::new(1.0, 1.0), 0.5, 1.0);
+        assert!(close(intercept(&path, 0.9).unwrap(), 0.1));
+        assert!(close(intercept(&path, 0.25).unwrap(), 0.25));
+        assert!(intercept(&path, -0.5).is_none());
+    }
+
+    #[test]
+    fn hard_paddles_steer_to_the_intercept() {
+        let config = GameConfig { wall: 0.5, goal_line: 1.0, ..GameConfig::default() };
+        let balls = [Ball::new(Vec2::zero(), Vec2::new(1.0, 1.0), 0)];
+        let mut right = Paddle::new(Vec2::new(0.9, -0.3), Track::Vertical, PADDLE_SHAPE);
+
+        // The ball bounces down to 0.1 by the time it reaches x = 0.9
+        control(&mut right, Difficulty::Hard, &balls, &config);
+        assert!(right.up && !right.down);
+        right.position.y = 0.3;
+        control(&mut right, Difficulty::Hard, &balls, &config);
+        assert!(!right.up && right.down);
+        right.position.y = 0.1;
+        control(&mut right, Difficulty::Hard, &balls, &config);
+        assert!(!right.up && !right.down);
+
+        // Easy paddles wait for the ball to come closer
+        right.position.y = -0.3;
+        control(&mut right, Difficulty::Easy, &balls, &config);
+        assert!(!right.up && !right.down);
+
+        // With no ball coming, paddles return to the middle
+        let mut left = Paddle::new(Vec2::new(-0.9, 0.3), Track::Vertical, PADDLE_SHAPE);
+        control(&mut left, Difficulty::Hard, &balls, &config);
+        assert!(!left.up && left.down);
+    }
+}
@@ -0,0 +1,425 @@
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGlTexture;
+
+use crate::{bounce, data, Ball, Bounce, Pong, RenderContext, Vec2};
+use crate::collision::{self, Shape};
+use crate::command::Commands;
+use crate::config::{from_js, GameConfig};
+use crate::error::PongError;
+use crate::gl::VertexArray;
+
+// Back walls beside narrow goal mouths stand at the edge of the field
+const BACK_WALL_X: f32 = 1.0;
+const WALL_WIDTH: f32 = 0.02;
+const CIRCLE_SEGMENTS: usize = 24;
+// Bumpers light up for this long after a hit, in milliseconds
+const FLASH_TIME: f32 = 150.0;
+
+// An arena as described in JSON: extra wall segments and obstacles on top of
+// the top and bottom walls, and the size of the goal mouths
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    pub name: String,
+    // Height of the goal mouths, centered on the goal lines. Back walls close
+    // the rest of each side.
+    pub goal_width: f32,
+    pub walls: Vec<Segment>,
+    pub obstacles: Vec<Obstacle>
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Segment {
+    pub from: [f32; 2],
+    pub to: [f32; 2]
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Obstacle {
+    pub position: [f32; 2],
+    pub shape: Shape,
+    // Fraction of the ball's speed kept when bouncing off, bumpers have more
+    // than 1
+    #[serde(default = "default_restitution")]
+    pub restitution: f32,
+    #[serde(default)]
+    pub motion: Option<Motion>
+}
+
+// Moves an obstacle back and forth between its position plus and minus
+// `offset`
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Motion {
+    pub offset: [f32; 2],
+    // Milliseconds for a full cycle
+    pub period: f32,
+    // Fraction of a cycle the obstacle starts at
+    #[serde(default)]
+    pub phase: f32
+}
+
+// The arena in play, with the registry of layouts to choose from
+pub struct Arena {
+    layouts: Vec<Layout>,
+    layout: Layout,
+    time: f32,
+    flashes: Vec<f32>
+}
+
+// WebGL resources for drawing arenas, kept apart from the arena so it can be
+// played without a context
+pub struct Graphics {
+    vertices: VertexArray,
+    wall: WebGlTexture,
+    obstacle: WebGlTexture,
+    bumper: WebGlTexture,
+    flash: WebGlTexture
+}
+
+fn default_restitution() -> f32 {
+    1.0
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout { name: String::new(), goal_width: 2.0, walls: Vec::new(), obstacles: Vec::new() }
+    }
+}
+
+impl Layout {
+    pub fn from_js(value: JsValue) -> Result<Layout, PongError> {
+        let layout: Layout = from_js(value, "arena")?;
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    fn validate(&self) -> Result<(), PongError> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push(String::from("name must not be empty"));
+        }
+        if !(self.goal_width.is_finite() && self.goal_width > 0.0) {
+            errors.push(format!("goal_width must be a positive number, got {}", self.goal_width));
+        }
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            let extent = obstacle.shape.extent();
+            if !(extent.x > 0.0 && extent.y > 0.0) {
+                errors.push(format!("obstacle {} must have a positive size", i));
+            }
+            if !(obstacle.restitution.is_finite() && obstacle.restitution > 0.0) {
+                errors.push(format!("obstacle {} restitution must be a positive number, got {}",
+                                    i, obstacle.restitution));
+            }
+            if let Some(motion) = &obstacle.motion {
+                if !(motion.period.is_finite() && motion.period > 0.0) {
+                    errors.push(format!("obstacle {} motion period must be a positive number, got {}",
+                                        i, motion.period));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(PongError::Config(format!("Invalid arena {}: {}", self.name, errors.join("; "))))
+        }
+    }
+}
+
+impl Obstacle {
+    fn position_at(&self, time: f32) -> Vec2 {
+        let [x, y] = self.position;
+        match &self.motion {
+            Some(motion) => {
+                let t = (time / motion.period + motion.phase) * std::f32::consts::TAU;
+                Vec2::new(x + motion.offset[0] * t.sin(), y + motion.offset[1] * t.sin())
+            },
+            None => Vec2::new(x, y)
+        }
+    }
+
+    fn is_bumper(&self) -> bool {
+        self.restitution > 1.0
+    }
+}
+
+impl Arena {
+    // Starts with the built-in layout named `name`
+    pub fn new(name: &str) -> Result<Arena, PongError> {
+        let layouts = builtin_layouts()?;
+        let layout = find(&layouts, name)?;
+        Ok(Arena { layouts, layout, time: 0.0, flashes: Vec::new() })
+    }
+
+    // Adds a layout to the registry, replacing any layout with the same name
+    pub fn register(&mut self, layout: Layout) {
+        self.layouts.retain(|l| l.name != layout.name);
+        self.layouts.push(layout);
+    }
+
+    // Switches to the registered layout named `name`, leaving the arena in
+    // play alone if it is the one
+    pub fn select(&mut self, name: &str) -> Result<(), PongError> {
+        if self.layout.name != name {
+            self.start(name)?;
+        }
+        Ok(())
+    }
+
+    // Registers a layout and starts playing it, even over an older version of
+    // the arena in play
+    pub fn load(&mut self, layout: Layout) -> Result<(), PongError> {
+        let name = layout.name.clone();
+        self.register(layout);
+        self.start(&name)
+    }
+
+    fn start(&mut self, name: &str) -> Result<(), PongError> {
+        self.layout = find(&self.layouts, name)?;
+        self.time = 0.0;
+        self.flashes.clear();
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.layout.name
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta;
+        self.flashes.resize(self.layout.obstacles.len(), 0.0);
+        for flash in &mut self.flashes {
+            *flash = (*flash - delta).max(0.0);
+        }
+    }
+
+    // Wall segments including the back walls beside the goal mouths
+    pub fn walls(&self, config: &GameConfig) -> Vec<(Vec2, Vec2)> {
+        let mut walls: Vec<(Vec2, Vec2)> = self.layout.walls.iter()
+            .map(|wall| (Vec2::new(wall.from[0], wall.from[1]), Vec2::new(wall.to[0], wall.to[1])))
+            .collect();
+        let mouth = self.layout.goal_width / 2.0;
+        if mouth < config.wall {
+            for x in [-BACK_WALL_X, BACK_WALL_X] {
+                walls.push((Vec2::new(x, mouth), Vec2::new(x, config.wall)));
+                walls.push((Vec2::new(x, -config.wall), Vec2::new(x, -mouth)));
+            }
+        }
+        walls
+    }
+
+    // Obstacle positions and shapes at the current time
+    pub fn obstacles(&self) -> Vec<(Vec2, Shape)> {
+        self.layout.obstacles.iter()
+            .map(|obstacle| (obstacle.position_at(self.time), obstacle.shape))
+            .collect()
+    }
+
+    // Bounces a ball off walls and obstacles, returning the bounces
+    pub fn collide(&mut self, ball: &mut Ball, config: &GameConfig) -> Vec<Bounce> {
+        let mut bounces = Vec::new();
+        for (a, b) in self.walls(config) {
+            if let Some(contact) = collision::collide_segment(&ball.position, &ball.shape, &a, &b) {
+                bounces.extend(bounce(ball, contact, 1.0));
+            }
+        }
+        for (i, obstacle) in self.layout.obstacles.iter().enumerate() {
+            let position = obstacle.position_at(self.time);
+            if let Some(contact) = collision::collide(&ball.position, &ball.shape, &position, &obstacle.shape) {
+                let bounced = bounce(ball, contact, obstacle.restitution);
+                if bounced.is_some() && obstacle.is_bumper() {
+                    self.flashes[i] = FLASH_TIME;
+                }
+                bounces.extend(bounced);
+            }
+        }
+        bounces
+    }
+
+    pub fn render(&self, graphics: &Graphics, ctx: &RenderContext, config: &GameConfig) {
+        let mut walls = Vec::new();
+        for (a, b) in self.walls(config) {
+            quad_line(&mut walls, &a, &b);
+        }
+        graphics.vertices.draw(ctx, &graphics.wall, &walls);
+
+        // Obstacles are drawn in three batches by how they look
+        let mut batches = [Vec::new(), Vec::new(), Vec::new()];
+        for (i, obstacle) in self.layout.obstacles.iter().enumerate() {
+            let batch = if !obstacle.is_bumper() {
+                0
+            } else if self.flashes.get(i).copied().unwrap_or(0.0) > 0.0 {
+                2
+            } else {
+                1
+            };
+            fill_shape(&mut batches[batch], &obstacle.position_at(self.time), &obstacle.shape);
+        }
+        for (vertices, texture) in batches.iter().zip([&graphics.obstacle, &graphics.bumper, &graphics.flash]) {
+            graphics.vertices.draw(ctx, texture, vertices);
+        }
+    }
+}
+
+impl Graphics {
+    pub fn new(ctx: &RenderContext) -> Result<Graphics, PongError> {
+        Ok(Graphics {
+            vertices: VertexArray::new(ctx)?,
+            wall: ctx.load_texture(&[0x88, 0x88, 0x88, 0xFF], 1, 1)?,
+            obstacle: ctx.load_texture(&[0x55, 0x66, 0x77, 0xFF], 1, 1)?,
+            bumper: ctx.load_texture(&[0x00, 0x99, 0xCC, 0xFF], 1, 1)?,
+            flash: ctx.load_texture(&[0xAA, 0xEE, 0xFF, 0xFF], 1, 1)?
+        })
+    }
+}
+
+fn quad_line(vertices: &mut Vec<f32>, a: &Vec2, b: &Vec2) {
+    let direction = Vec2::new(b.x - a.x, b.y - a.y).normalized();
+    let (nx, ny) = (-direction.y * WALL_WIDTH / 2.0, direction.x * WALL_WIDTH / 2.0);
+    vertices.extend_from_slice(&[
+        a.x + nx, a.y + ny, 0.5, 0.5,  a.x - nx, a.y - ny, 0.5, 0.5,  b.x - nx, b.y - ny, 0.5, 0.5,
+        a.x + nx, a.y + ny, 0.5, 0.5,  b.x - nx, b.y - ny, 0.5, 0.5,  b.x + nx, b.y + ny, 0.5, 0.5
+    ]);
+}
+
+pub fn fill_rect(vertices: &mut Vec<f32>, center: &Vec2, half_width: f32, half_height: f32) {
+    let (x0, x1) = (center.x - half_width, center.x + half_width);
+    let (y0, y1) = (center.y - half_height, center.y + half_height);
+    vertices.extend_from_slice(&[
+        x0, y0, 0.5, 0.5,  x1, y1, 0.5, 0.5,  x0, y1, 0.5, 0.5,
+        x0, y0, 0.5, 0.5,  x1, y0, 0.5, 0.5,  x1, y1, 0.5, 0.5
+    ]);
+}
+
+fn fill_circle(vertices: &mut Vec<f32>, center: &Vec2, radius: f32) {
+    let point = |i: usize| {
+        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+        (center.x + radius * angle.cos(), center.y + radius * angle.sin())
+    };
+    for i in 0..CIRCLE_SEGMENTS {
+        let (ax, ay) = point(i);
+        let (bx, by) = point(i + 1);
+        vertices.extend_from_slice(&[center.x, center.y, 0.5, 0.5,  ax, ay, 0.5, 0.5,  bx, by, 0.5, 0.5]);
+    }
+}
+
+fn fill_shape(vertices: &mut Vec<f32>, position: &Vec2, shape: &Shape) {
+    match *shape {
+        Shape::Aabb { half_width, half_height } => fill_rect(vertices, position, half_width, half_height),
+        Shape::Circle { radius } => fill_circle(vertices, position, radius),
+        Shape::Capsule { half_length, radius } => {
+            fill_rect(vertices, position, radius, half_length);
+            fill_circle(vertices, &Vec2::new(position.x, position.y + half_length), radius);
+            fill_circle(vertices, &Vec2::new(position.x, position.y - half_length), radius);
+        }
+    }
+}
+
+fn builtin_layouts() -> Result<Vec<Layout>, PongError> {
+    let layouts: Vec<Layout> = serde_json::from_str(data::ARENAS)
+        .map_err(|e| PongError::Config(format!("Invalid built-in arenas: {}", e)))?;
+    for layout in &layouts {
+        layout.validate()?;
+    }
+    Ok(layouts)
+}
+
+fn find(layouts: &[Layout], name: &str) -> Result<Layout, PongError> {
+    layouts.iter().find(|layout| layout.name == name).cloned().ok_or_else(|| {
+        let names: Vec<&str> = layouts.iter().map(|layout| layout.name.as_str()).collect();
+        PongError::Config(format!("Unknown arena {}, expected one of {}", name, names.join(", ")))
+    })
+}
+
+pub fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("arena", "arena [name]", |pong, args| {
+        match args.first() {
+            Some(name) => {
+                pong.arena.select(name).map_err(|e| String::from(e.message()))?;
+                pong.config.arena = String::from(*name);
+                Ok(format!("Arena {}", name))
+            },
+            None => {
+                let names: Vec<&str> = pong.arena.layouts.iter().map(|layout| layout.name.as_str()).collect();
+                Ok(names.join(" "))
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(json: &str) -> Layout {
+        let layout: Layout = serde_json::from_str(json).unwrap();
+        layout.validate().unwrap();
+        layout
+    }
+
+    #[test]
+    fn moving_obstacles_swing_around_their_position() {
+        let layout = layout(r#"{ "name": "swing", "obstacles": [
+            { "position": [0.1, 0.2], "shape": { "circle": { "radius": 0.05 } },
+              "motion": { "offset": [0.3, 0.0], "period": 1000 } }
+        ] }"#);
+        let obstacle = &layout.obstacles[0];
+        for (time, x) in [(0.0, 0.1), (250.0, 0.4), (500.0, 0.1), (750.0, -0.2), (1000.0, 0.1)] {
+            let position = obstacle.position_at(time);
+            assert!((position.x - x).abs() < 1e-5 && position.y == 0.2, "at {} ms x = {}", time, position.x);
+        }
+    }
+
+    #[test]
+    fn bumpers_send_balls_back_faster_and_flash() {
+        let config = GameConfig::default();
+        let mut arena = Arena::new("classic").unwrap();
+        arena.load(layout(r#"{ "name": "bumpy", "obstacles": [
+            { "position": [-0.5, 0.0], "shape": { "circle": { "radius": 0.1 } } },
+            { "position": [0.5, 0.0], "shape": { "circle": { "radius": 0.1 } }, "restitution": 1.5 }
+        ] }"#)).unwrap();
+        arena.update(0.0);
+
+        let mut ball = Ball::new(Vec2::new(-0.36, 0.0), Vec2::new(-1.0, 0.0), 0);
+        assert_eq!(arena.collide(&mut ball, &config).len(), 1);
+        assert!((ball.velocity.x - 1.0).abs() < 1e-6);
+        assert_eq!(arena.flashes[0], 0.0);
+
+        let mut ball = Ball::new(Vec2::new(0.36, 0.0), Vec2::new(1.0, 0.0), 0);
+        assert_eq!(arena.collide(&mut ball, &config).len(), 1);
+        assert!((ball.velocity.x + 1.5).abs() < 1e-6);
+        assert_eq!(arena.flashes[1], FLASH_TIME);
+        arena.update(FLASH_TIME);
+        assert_eq!(arena.flashes[1], 0.0);
+    }
+
+    #[test]
+    fn reloading_the_active_arena_replaces_it() {
+        let mut arena = Arena::new("classic").unwrap();
+        arena.load(layout(r#"{ "name": "custom", "obstacles": [
+            { "position": [0.0, 0.0], "shape": { "circle": { "radius": 0.1 } }, "restitution": 1.5 }
+        ] }"#)).unwrap();
+        arena.update(500.0);
+        arena.flashes[0] = FLASH_TIME;
+
+        arena.load(layout(r#"{ "name": "custom", "goal_width": 1.0, "obstacles": [
+            { "position": [0.2, 0.0], "shape": { "circle": { "radius": 0.1 } } },
+            { "position": [-0.2, 0.0], "shape": { "circle": { "radius": 0.1 } } }
+        ] }"#)).unwrap();
+        assert_eq!(arena.name(), "custom");
+        assert_eq!(arena.obstacles().len(), 2);
+        assert_eq!(arena.layout.goal_width, 1.0);
+        assert_eq!(arena.time, 0.0);
+        assert!(arena.flashes.is_empty());
+
+        // Selecting the arena in play keeps it going
+        arena.update(500.0);
+        arena.select("custom").unwrap();
+        assert_eq!(arena.time, 500.0);
+        assert!(arena.select("missing").is_err());
+    }
+}
@@ -0,0 +1,511 @@
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGlTexture;
+
+use crate::{ai, bounce, data, hit_paddle, hit_wall, multiball, paddle_shape, play_audio, reflect_off_side, Ball,
+            Bounce, Models, Paddle, Pong, RenderContext, Side, Vec2, BALL_SHAPE};
+use crate::arena::fill_rect;
+use crate::collision::{self, Shape};
+use crate::command::Commands;
+use crate::config::{from_js, BreakoutPaddle, GameConfig, Mode};
+use crate::error::PongError;
+use crate::events::GameEvent;
+use crate::gl::VertexArray;
+use crate::text::TextRenderer;
+
+// Bricks start this far in from the wall opposite the paddle
+const GRID_MARGIN: f32 = 0.1;
+const BRICK_DEPTH: f32 = 0.07;
+const BRICK_GAP: f32 = 0.01;
+// Directions sparks fly in when a brick breaks
+const BURST: [(f32, f32); 4] = [(1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)];
+// Serves start this far in front of the paddle
+const SERVE_DISTANCE: f32 = 0.15;
+const TEXT_SIZE: f32 = 0.04;
+
+// A level as described in JSON: rows of bricks from the far wall towards the
+// paddle, each digit a brick taking that many hits and dots gaps
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub name: String,
+    pub rows: Vec<String>
+}
+
+struct Brick {
+    position: Vec2,
+    shape: Shape,
+    hits: u32
+}
+
+// Breakout mode: a single paddle clears a grid of bricks on the far side of
+// the field, losing a life whenever the ball gets past it. Cleared levels
+// lead on to the next one.
+pub struct Breakout {
+    levels: Vec<Level>,
+    level: usize,
+    bricks: Vec<Brick>,
+    side: Side,
+    lives: u32,
+    score: u32
+}
+
+// WebGL resources for drawing bricks, kept apart so the game can be played
+// without a context
+pub struct Graphics {
+    vertices: VertexArray,
+    // Brick colors by hits left, the last for all tougher bricks
+    bricks: [WebGlTexture; 3]
+}
+
+impl Level {
+    pub fn from_js(value: JsValue, config: &GameConfig) -> Result<Level, PongError> {
+        let level: Level = from_js(value, "level")?;
+        level.validate()?;
+        // Other modes don't lay out bricks, the field is checked when
+        // switching to breakout
+        if config.mode == Mode::Breakout {
+            level.fit(config)?;
+        }
+        Ok(level)
+    }
+
+    fn validate(&self) -> Result<(), PongError> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push(String::from("name must not be empty"));
+        }
+        for (i, row) in self.rows.iter().enumerate() {
+            if let Some(c) = row.chars().find(|c| *c != '.' && !('1'..='9').contains(c)) {
+                errors.push(format!("row {} has {:?}, expected digits 1 to 9 or dots", i, c));
+            }
+        }
+        if !self.rows.iter().any(|row| row.chars().any(|c| c != '.')) {
+            errors.push(String::from("level must have at least one brick"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(PongError::Config(format!("Invalid level {}: {}", self.name, errors.join("; "))))
+        }
+    }
+
+    // Checks that the lowest row leaves room to serve in front of the paddle
+    fn fit(&self, config: &GameConfig) -> Result<(), PongError> {
+        let lowest = config.wall - GRID_MARGIN - self.rows.len() as f32 * BRICK_DEPTH;
+        let serve = -config.paddle_x + SERVE_DISTANCE + 2.0 * BALL_SHAPE.extent().x;
+        if lowest < serve {
+            let fitting = ((config.wall - GRID_MARGIN - serve) / BRICK_DEPTH).floor().max(0.0);
+            return Err(PongError::Config(format!("Invalid level {}: level has {} rows, at most {} fit in front \
+                                                  of the paddle", self.name, self.rows.len(), fitting)));
+        }
+        Ok(())
+    }
+
+    // Lays the bricks out across the far side of the field from the paddle
+    // on `side`
+    fn bricks(&self, side: Side, config: &GameConfig) -> Vec<Brick> {
+        let outward = side.outward();
+        // Across the field, from the end the rows are read from
+        let across = Vec2::new(-outward.y, outward.x);
+        let columns = self.rows.iter().map(|row| row.len()).max().unwrap_or(1).max(1);
+        let width = 2.0 * config.wall / columns as f32;
+        let (half_width, half_depth) = ((width - BRICK_GAP) / 2.0, (BRICK_DEPTH - BRICK_GAP) / 2.0);
+        let shape = if outward.x == 0.0 {
+            Shape::Aabb { half_width, half_height: half_depth }
+        } else {
+            Shape::Aabb { half_width: half_depth, half_height: half_width }
+        };
+
+        let mut bricks = Vec::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            let depth = config.wall - GRID_MARGIN - (i as f32 + 0.5) * BRICK_DEPTH;
+            for (j, c) in row.chars().enumerate() {
+                let hits = match c.to_digit(10) {
+                    Some(hits) if hits > 0 => hits,
+                    _ => continue
+                };
+                let offset = -config.wall + (j as f32 + 0.5) * width;
+                let position = Vec2::new(across.x * offset - outward.x * depth, across.y * offset - outward.y * depth);
+                bricks.push(Brick { position, shape, hits });
+            }
+        }
+        bricks
+    }
+}
+
+impl Breakout {
+    pub fn new(config: &GameConfig) -> Result<Breakout, PongError> {
+        let mut breakout = Breakout {
+            levels: builtin_levels()?,
+            level: 0,
+            bricks: Vec::new(),
+            side: side(config),
+            lives: config.lives,
+            score: 0
+        };
+        breakout.start(config);
+        Ok(breakout)
+    }
+
+    // Goes back to the first level with full lives
+    pub fn reset(&mut self, config: &GameConfig) {
+        self.level = 0;
+        self.lives = config.lives;
+        self.score = 0;
+        self.start(config);
+    }
+
+    // Lays out the current level's bricks
+    fn start(&mut self, config: &GameConfig) {
+        self.side = side(config);
+        self.bricks = self.levels[self.level].bricks(self.side, config);
+    }
+
+    // Turns the bricks to face the configured paddle
+    pub fn apply_config(&mut self, config: &GameConfig) {
+        if side(config) != self.side {
+            self.start(config);
+        }
+    }
+
+    // Checks that every level fits the field of `config`, before switching to
+    // breakout
+    pub fn check(&self, config: &GameConfig) -> Result<(), PongError> {
+        self.levels.iter().try_for_each(|level| level.fit(config))
+    }
+
+    // Adds a level after the others, or replaces the one with the same name
+    pub fn register(&mut self, level: Level) {
+        match self.levels.iter_mut().find(|l| l.name == level.name) {
+            Some(existing) => *existing = level,
+            None => self.levels.push(level)
+        }
+    }
+
+    // Restarts the level named `name`
+    pub fn select(&mut self, name: &str, config: &GameConfig) -> Result<(), PongError> {
+        self.level = self.levels.iter().position(|level| level.name == name).ok_or_else(|| {
+            let names: Vec<&str> = self.levels.iter().map(|level| level.name.as_str()).collect();
+            PongError::Config(format!("Unknown level {}, expected one of {}", name, names.join(", ")))
+        })?;
+        self.start(config);
+        Ok(())
+    }
+
+    // Bounces a ball off the bricks it touches, taking a hit off each.
+    // Returns the bounces and where bricks broke.
+    fn hit_bricks(&mut self, ball: &mut Ball) -> (Vec<Bounce>, Vec<Vec2>) {
+        let mut impacts = Vec::new();
+        let mut broken = Vec::new();
+        for brick in self.bricks.iter_mut().filter(|brick| brick.hits > 0) {
+            let impact = match collision::collide(&ball.position, &ball.shape, &brick.position, &brick.shape)
+                .and_then(|contact| bounce(ball, contact, 1.0)) {
+                Some(impact) => impact,
+                None => continue
+            };
+            brick.hits -= 1;
+            ball.spin = -ball.spin;
+            impacts.push(impact);
+            if brick.hits == 0 {
+                self.score += 1;
+                broken.push(brick.position.clone());
+            }
+        }
+        (impacts, broken)
+    }
+
+    // Moves on to the next level once every brick is gone, returning the
+    // number of the level cleared
+    fn advance(&mut self, config: &GameConfig) -> Option<u32> {
+        if !self.bricks.is_empty() {
+            return None;
+        }
+        let cleared = self.level as u32 + 1;
+        self.level = (self.level + 1) % self.levels.len();
+        self.start(config);
+        Some(cleared)
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    // Brick positions and shapes
+    pub fn bricks(&self) -> impl Iterator<Item = (&Vec2, &Shape)> {
+        self.bricks.iter().map(|brick| (&brick.position, &brick.shape))
+    }
+
+    // Draws the bricks, the bottom paddle when it is the one in play, and the
+    // level and lives left
+    pub fn render(&self, graphics: &Graphics, bottom: &Paddle, models: &Models, ctx: &RenderContext) {
+        let mut batches = [Vec::new(), Vec::new(), Vec::new()];
+        for brick in &self.bricks {
+            let extent = brick.shape.extent();
+            let batch = (brick.hits.min(batches.len() as u32) - 1) as usize;
+            fill_rect(&mut batches[batch], &brick.position, extent.x, extent.y);
+        }
+        for (vertices, texture) in batches.iter().zip(&graphics.bricks) {
+            graphics.vertices.draw(ctx, texture, vertices);
+        }
+
+        if self.side == Side::Bottom {
+            models.paddle.pre_render(ctx);
+            models.paddle.render_rotated(&bottom.position, &Vec2::new(1.0, 1.0), std::f32::consts::FRAC_PI_2, ctx);
+        }
+
+        let text = format!("LEVEL {} LIVES {}", self.level + 1, self.lives);
+        let x = -TextRenderer::width(&text, TEXT_SIZE) / 2.0;
+        models.text.render(ctx, &text, x, 0.99, TEXT_SIZE, 0.6);
+    }
+}
+
+impl Graphics {
+    pub fn new(ctx: &RenderContext) -> Result<Graphics, PongError> {
+        Ok(Graphics {
+            vertices: VertexArray::new(ctx)?,
+            bricks: [
+                ctx.load_texture(&[0x00, 0xAA, 0xDD, 0xFF], 1, 1)?,
+                ctx.load_texture(&[0xEE, 0xAA, 0x00, 0xFF], 1, 1)?,
+                ctx.load_texture(&[0xDD, 0x33, 0x44, 0xFF], 1, 1)?
+            ]
+        })
+    }
+}
+
+fn side(config: &GameConfig) -> Side {
+    match config.breakout_paddle {
+        BreakoutPaddle::Left => Side::Left,
+        BreakoutPaddle::Bottom => Side::Bottom
+    }
+}
+
+// Advances a breakout game by `delta` milliseconds of game time
+pub fn update(pong: &mut Pong, delta: f32, timestamp: i32) {
+    let config = &pong.config;
+    let breakout = &mut pong.breakout;
+    let side = breakout.side;
+    let paddle = if side == Side::Left { &mut pong.left } else { &mut pong.square.bottom };
+
+    if let Some(difficulty) = paddle.ai {
+        ai::control(paddle, difficulty, &pong.balls, config);
+    }
+    let direction = paddle.direction();
+    paddle.steer(direction, config.paddle_speed, delta, config.paddle_limit);
+    paddle.set_shape(paddle_shape(config));
+
+    let mut paddle_hits = Vec::new();
+    let mut wall_bounces = Vec::new();
+    for ball in pong.balls.iter_mut() {
+        ball.advance(delta, config.ball_speed, config);
+
+        if let Some(impact) = paddle.contact(ball).and_then(|contact| bounce(ball, contact, 1.0)) {
+            paddle.strike(ball, &impact, config);
+            ball.last_hitter = Some(side);
+            paddle_hits.push(impact);
+        }
+
+        let (impacts, broken) = breakout.hit_bricks(ball);
+        for impact in impacts {
+            play_audio(&pong.audio, &pong.boop);
+            pong.recorder.impact(impact.position.x, impact.position.y, impact.direction.x, impact.direction.y);
+        }
+        for position in broken {
+            for (dx, dy) in BURST {
+                pong.recorder.impact(position.x, position.y, dx, dy);
+            }
+        }
+
+        // Every side but the paddle's is a wall
+        for wall in [Side::Left, Side::Right, Side::Top, Side::Bottom] {
+            if wall == side {
+                continue;
+            }
+            if let Some(bounce) = reflect_off_side(ball, wall, config) {
+                wall_bounces.push((wall, bounce));
+            }
+        }
+
+        ball.tail.add(ball.position.clone(), Vec2::zero(), Vec2::zero(), 1000.0);
+        ball.tail.update(delta);
+    }
+
+    for impact in paddle_hits {
+        hit_paddle(pong, side, &impact);
+    }
+    for (wall, bounce) in wall_bounces {
+        hit_wall(pong, wall, &bounce);
+    }
+    let paddles = pong.side_paddles();
+    let config = &pong.config;
+    let breakout = &mut pong.breakout;
+
+    let before = breakout.bricks.len();
+    breakout.bricks.retain(|brick| brick.hits > 0);
+    for remaining in (breakout.bricks.len()..before).rev() {
+        pong.events.push(GameEvent::BrickDestroyed { remaining: remaining as u32 });
+    }
+
+    pong.recorder.create_sparks(&mut pong.sparks, config.sparks_per_hit);
+    pong.sparks.update(delta);
+    pong.recorder.record(delta, &pong.balls, paddles);
+
+    let outward = side.outward();
+    let served = pong.balls.len();
+    pong.balls.retain(|ball| ball.position.dot(&outward) <= config.goal_line);
+    if pong.balls.is_empty() && served > 0 {
+        breakout.lives -= 1;
+        pong.events.push(GameEvent::LifeLost { side, lives: breakout.lives });
+        pong.events.push(GameEvent::RallyEnded { length: pong.rally });
+        if breakout.lives == 0 {
+            pong.events.push(GameEvent::GameOver { level: breakout.level as u32 + 1, score: breakout.score });
+            breakout.reset(config);
+            if let Some(audio) = &mut pong.audio {
+                audio.music.start(&audio.ctx);
+            }
+        }
+    }
+
+    if let Some(level) = breakout.advance(config) {
+        pong.events.push(GameEvent::LevelCleared { level });
+        pong.balls.clear();
+    }
+
+    if pong.balls.is_empty() {
+        pong.rally = 0;
+        let paddle = if side == Side::Left { &pong.left } else { &pong.square.bottom };
+        // Serves head for the bricks at an angle
+        let along = multiball::serve_direction(timestamp).y;
+        let velocity = Vec2::new(-outward.y * along - outward.x, outward.x * along - outward.y);
+        let position = Vec2::new(paddle.position.x - outward.x * SERVE_DISTANCE,
+                                 paddle.position.y - outward.y * SERVE_DISTANCE);
+        pong.events.push(GameEvent::Serve { dx: velocity.x, dy: velocity.y });
+        pong.balls.push(Ball::new(position, velocity, config.ball_tail_particles));
+        play_audio(&pong.audio, &pong.bloop);
+    }
+    pong.scoreboard.set(breakout.score, breakout.lives);
+}
+
+pub fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("level", "level [name]", |pong, args| {
+        match args.first() {
+            Some(name) => {
+                pong.breakout.select(name, &pong.config).map_err(|e| String::from(e.message()))?;
+                Ok(format!("Level {}", name))
+            },
+            None => {
+                let names: Vec<&str> = pong.breakout.levels.iter().map(|level| level.name.as_str()).collect();
+                Ok(names.join(" "))
+            }
+        }
+    });
+}
+
+fn builtin_levels() -> Result<Vec<Level>, PongError> {
+    let levels: Vec<Level> = serde_json::from_str(data::LEVELS)
+        .map_err(|e| PongError::Config(format!("Invalid built-in levels: {}", e)))?;
+    for level in &levels {
+        level.validate()?;
+    }
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_levels_fit_the_field() {
+        let config = GameConfig::default();
+        let breakout = Breakout::new(&config).unwrap();
+        for level in &breakout.levels {
+            for side in [Side::Left, Side::Bottom] {
+                for brick in level.bricks(side, &config) {
+                    let extent = brick.shape.extent();
+                    assert!(brick.position.x.abs() + extent.x <= config.wall, "Level {} overflows", level.name);
+                    assert!(brick.position.y.abs() + extent.y <= config.wall, "Level {} overflows", level.name);
+                }
+            }
+        }
+
+        // Rows reaching down to the paddle could never be cleared
+        let oversized = Level { name: String::from("tower"), rows: vec![String::from("1111"); 30] };
+        assert!(oversized.validate().is_ok());
+        assert!(oversized.fit(&config).is_err());
+        let fitting = Level { name: String::from("tower"), rows: vec![String::from("1111"); 12] };
+        assert!(fitting.fit(&config).is_ok());
+        let serve = -config.paddle_x + SERVE_DISTANCE;
+        for side in [Side::Left, Side::Bottom] {
+            for brick in fitting.bricks(side, &config) {
+                let depth = -brick.position.dot(&side.outward());
+                assert!(depth - brick.shape.extent().x.min(brick.shape.extent().y) > serve);
+            }
+        }
+    }
+
+    #[test]
+    fn levels_only_need_to_fit_the_field_in_breakout() {
+        let small = GameConfig { wall: 0.4, paddle_x: 0.3, doubles_depth: 0.2, ..GameConfig::default() };
+        small.validate().unwrap();
+        let breakout = Breakout::new(&small).unwrap();
+        let error = breakout.check(&small).unwrap_err();
+        assert!(error.message().contains("fit in front of the paddle"), "{}", error.message());
+        assert!(breakout.check(&GameConfig::default()).is_ok());
+    }
+
+    fn breakout_with(rows: &[&str], config: &GameConfig) -> Breakout {
+        let mut breakout = Breakout::new(config).unwrap();
+        let level = Level { name: String::from("test"), rows: rows.iter().map(|row| String::from(*row)).collect() };
+        level.validate().unwrap();
+        level.fit(config).unwrap();
+        breakout.register(level);
+        breakout.select("test", config).unwrap();
+        breakout
+    }
+
+    #[test]
+    fn bricks_break_after_their_hits() {
+        let config = GameConfig::default();
+        let mut breakout = breakout_with(&["2"], &config);
+        let (position, shape) = breakout.bricks().next().map(|(p, s)| (p.clone(), *s)).unwrap();
+        let below = position.y - shape.extent().y - 0.04;
+
+        let mut ball = Ball::new(Vec2::new(0.0, below), Vec2::new(0.5, 1.0), 0);
+        let (impacts, broken) = breakout.hit_bricks(&mut ball);
+        assert_eq!((impacts.len(), broken.len()), (1, 0));
+        assert_eq!(ball.velocity.y, -1.0);
+        assert_eq!(breakout.bricks[0].hits, 1);
+        assert_eq!(breakout.score, 0);
+
+        // Balls moving away from a brick don't hit it
+        assert!(breakout.hit_bricks(&mut ball).0.is_empty());
+
+        let mut ball = Ball::new(Vec2::new(0.0, below), Vec2::new(0.0, 1.0), 0);
+        let (impacts, broken) = breakout.hit_bricks(&mut ball);
+        assert_eq!((impacts.len(), broken.len()), (1, 1));
+        assert_eq!(broken[0].y, position.y);
+        assert_eq!(breakout.bricks[0].hits, 0);
+        assert_eq!(breakout.score, 1);
+    }
+
+    #[test]
+    fn clearing_the_last_brick_moves_on_to_the_next_level() {
+        let config = GameConfig::default();
+        let mut breakout = breakout_with(&["1.1"], &config);
+        let last = breakout.levels.len() - 1;
+        assert_eq!(breakout.level, last);
+        assert!(breakout.advance(&config).is_none());
+
+        breakout.bricks.truncate(1);
+        assert!(breakout.advance(&config).is_none());
+        breakout.bricks.clear();
+        assert_eq!(breakout.advance(&config), Some(last as u32 + 1));
+
+        // The levels start over after the last one
+        assert_eq!(breakout.level, 0);
+        let expected = breakout.levels[0].bricks(breakout.side, &config).len();
+        assert_eq!(breakout.bricks.len(), expected);
+        assert!(expected > 0);
+    }
+}
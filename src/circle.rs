@@ -0,0 +1,183 @@
+use std::f32::consts::{PI, TAU};
+use web_sys::WebGlTexture;
+
+use crate::{bounce, paddle_shape, Ball, Bounce, Paddle, RenderContext, Side, Track, Vec2};
+use crate::collision::{self, Contact, Shape};
+use crate::config::GameConfig;
+use crate::error::PongError;
+use crate::gl::VertexArray;
+
+const WALL_WIDTH: f32 = 0.02;
+const RING_WIDTH: f32 = 0.005;
+// Arcs are drawn as straight pieces this many radians long
+const ARC_STEP: f32 = 0.05;
+
+// Circular mode: both paddles ride a circle of radius `paddle_x`, the left one
+// around angle π and the right one around 0. The stretches of the circle out
+// of their reach are walls, and a ball leaving the circle anywhere else
+// scores for the opponent.
+pub struct CircleArena {
+    vertices: VertexArray,
+    wall: WebGlTexture,
+    ring: WebGlTexture
+}
+
+impl CircleArena {
+    pub fn new(ctx: &RenderContext) -> Result<CircleArena, PongError> {
+        Ok(CircleArena {
+            vertices: VertexArray::new(ctx)?,
+            wall: ctx.load_texture(&[0x88, 0x88, 0x88, 0xFF], 1, 1)?,
+            ring: ctx.load_texture(&[0x88, 0x88, 0x88, 0x55], 1, 1)?
+        })
+    }
+
+    pub fn reload(&mut self, ctx: &RenderContext) -> Result<(), PongError> {
+        *self = CircleArena::new(ctx)?;
+        Ok(())
+    }
+
+    // Draws the walls and the paddles' track
+    pub fn render(&self, ctx: &RenderContext, config: &GameConfig) {
+        let mut ring = Vec::new();
+        arc(&mut ring, config.paddle_x, 0.0, TAU, RING_WIDTH);
+        self.vertices.draw(ctx, &self.ring, &ring);
+
+        let mut walls = Vec::new();
+        let reach = reach(config);
+        if reach < PI / 2.0 {
+            let radius = config.wall + WALL_WIDTH / 2.0;
+            arc(&mut walls, radius, reach, PI - reach, WALL_WIDTH);
+            arc(&mut walls, radius, PI + reach, TAU - reach, WALL_WIDTH);
+        }
+        self.vertices.draw(ctx, &self.wall, &walls);
+    }
+}
+
+// Arc track of the paddle on `side`
+pub fn track(config: &GameConfig, side: Side) -> Track {
+    let home = if side == Side::Left { PI } else { 0.0 };
+    Track::Arc { radius: config.paddle_x, home }
+}
+
+// Angle of `v` from `home`, positive towards positive y
+pub fn offset(v: &Vec2, home: f32) -> f32 {
+    wrap(v.y.atan2(v.x) - home) * handedness(home)
+}
+
+// Point on the circle `offset` radians from `home`, positive towards positive y
+pub fn point(radius: f32, home: f32, offset: f32) -> Vec2 {
+    let angle = home + offset * handedness(home);
+    Vec2::new(radius * angle.cos(), radius * angle.sin())
+}
+
+fn handedness(home: f32) -> f32 {
+    if home.cos() < 0.0 { -1.0 } else { 1.0 }
+}
+
+fn wrap(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+// Angle from either paddle's home beyond which the circle is a wall
+fn reach(config: &GameConfig) -> f32 {
+    (config.paddle_limit + paddle_shape(config).extent().y) / config.paddle_x
+}
+
+// Angle of the paddle's center and half the angle it covers
+pub fn span(paddle: &Paddle, radius: f32) -> (f32, f32) {
+    (paddle.position.y.atan2(paddle.position.x), paddle.shape.extent().y / radius)
+}
+
+// Collides a ball with a paddle bent along a circle of `radius`. The inside of
+// the arc pushes the ball back towards the center, so balls hit off center
+// leave at an angle. The ends are rounded.
+pub fn collide_paddle(ball: &Ball, paddle: &Paddle, radius: f32) -> Option<Contact> {
+    let (center, half_angle) = span(paddle, radius);
+    let half_width = paddle.shape.extent().x;
+    let distance = ball.position.length();
+    let ball_radius = ball.shape.extent().x;
+    let angle = ball.position.y.atan2(ball.position.x);
+
+    if wrap(angle - center).abs() <= half_angle {
+        // Only the inner face pushes back, a ball already past the track got
+        // by the paddle
+        let depth = distance + ball_radius - (radius - half_width);
+        if depth <= 0.0 || distance >= radius || distance == 0.0 {
+            return None;
+        }
+        let normal = Vec2::new(-ball.position.x / distance, -ball.position.y / distance);
+        return Some(Contact { normal, depth });
+    }
+
+    let end = Shape::Circle { radius: half_width };
+    [center - half_angle, center + half_angle].iter().find_map(|angle| {
+        let position = Vec2::new(radius * angle.cos(), radius * angle.sin());
+        collision::collide(&ball.position, &ball.shape, &position, &end)
+    })
+}
+
+// Bounces a ball off the walls between the paddles' tracks, returning whether
+// it hit the top wall and the bounce
+pub fn collide_walls(ball: &mut Ball, config: &GameConfig) -> Option<(bool, Bounce)> {
+    let distance = ball.position.length();
+    let depth = distance + ball.shape.extent().x - config.wall;
+    let angle = ball.position.y.atan2(ball.position.x).abs();
+    if depth <= 0.0 || angle.min(PI - angle) <= reach(config) {
+        return None;
+    }
+    let normal = Vec2::new(-ball.position.x / distance, -ball.position.y / distance);
+    let top = ball.position.y > 0.0;
+    bounce(ball, Contact { normal, depth }, 1.0).map(|bounce| (top, bounce))
+}
+
+// Whether the ball has left the circle
+pub fn out(ball: &Ball, config: &GameConfig) -> bool {
+    ball.position.length() > config.goal_line
+}
+
+fn arc(vertices: &mut Vec<f32>, radius: f32, from: f32, to: f32, width: f32) {
+    let steps = ((to - from) / ARC_STEP).ceil().max(1.0) as usize;
+    let (inner, outer) = (radius - width / 2.0, radius + width / 2.0);
+    for i in 0..steps {
+        let a = from + (to - from) * i as f32 / steps as f32;
+        let b = from + (to - from) * (i + 1) as f32 / steps as f32;
+        let (ca, sa, cb, sb) = (a.cos(), a.sin(), b.cos(), b.sin());
+        vertices.extend_from_slice(&[
+            inner * ca, inner * sa, 0.5, 0.5,  outer * ca, outer * sa, 0.5, 0.5,  outer * cb, outer * sb, 0.5, 0.5,
+            inner * ca, inner * sa, 0.5, 0.5,  outer * cb, outer * sb, 0.5, 0.5,  inner * cb, inner * sb, 0.5, 0.5
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_are_positive_towards_the_top() {
+        for home in [0.0, PI] {
+            let above = point(0.9, home, 0.3);
+            assert!(above.y > 0.0);
+            assert!((offset(&above, home) - 0.3).abs() < 1e-5);
+            assert!((above.length() - 0.9).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn balls_past_the_track_are_not_saved() {
+        let config = GameConfig::default();
+        let paddle = Paddle::new(Vec2::new(config.paddle_x, 0.0), track(&config, Side::Right), paddle_shape(&config));
+        let radius = config.paddle_x;
+
+        let inside = Ball::new(Vec2::new(radius - 0.06, 0.0), Vec2::new(1.0, 0.0), 0);
+        let contact = collide_paddle(&inside, &paddle, radius).expect("inner face contact");
+        assert!(contact.normal.x < -0.99 && contact.depth > 0.0);
+
+        let mut past = Ball::new(Vec2::new(radius + 0.01, 0.0), Vec2::new(1.0, 0.0), 0);
+        assert!(collide_paddle(&past, &paddle, radius).is_none());
+        // Contacts the ball is already moving away from leave it where it is
+        let contact = Contact { normal: Vec2::new(1.0, 0.0), depth: 0.1 };
+        assert!(bounce(&mut past, contact, 1.0).is_none());
+        assert_eq!(past.position.x, radius + 0.01);
+    }
+}
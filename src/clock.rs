@@ -0,0 +1,108 @@
+use crate::Pong;
+use crate::command::{arg, Commands};
+
+// Simulated milliseconds per frame in step mode
+const STEP_DELTA: f32 = 1000.0 / 60.0;
+const MAX_TIME_SCALE: f32 = 10.0;
+
+// Turns wall clock frame times into simulation time, applying the global time
+// scale, slow motion, hit-stop freezes and frame-by-frame stepping
+pub struct Clock {
+    pub scale: f32,
+    hit_stop: f32,
+    stepping: bool,
+    steps: u32
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock { scale: 1.0, hit_stop: 0.0, stepping: false, steps: 0 }
+    }
+}
+
+impl Clock {
+    // Simulation time to advance by for a frame that took `delta` milliseconds,
+    // with `slow_motion` scaling time on top of the global time scale
+    pub fn advance(&mut self, delta: f32, slow_motion: f32) -> f32 {
+        if self.stepping {
+            if self.steps == 0 {
+                return 0.0;
+            }
+            self.steps -= 1;
+            return STEP_DELTA * self.scale;
+        }
+        if self.hit_stop > 0.0 {
+            self.hit_stop -= delta;
+            return 0.0;
+        }
+        delta * self.scale * slow_motion
+    }
+
+    // Freezes the simulation for `milliseconds` of wall clock time
+    pub fn hit_stop(&mut self, milliseconds: f32) {
+        self.hit_stop = self.hit_stop.max(milliseconds);
+    }
+}
+
+pub fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("timescale", "timescale <scale>", |pong, args| {
+        let scale: f32 = arg(args[0], "time scale")?;
+        if !(scale > 0.0 && scale <= MAX_TIME_SCALE) {
+            return Err(format!("Time scale must be above 0 and at most {}", MAX_TIME_SCALE));
+        }
+        pong.clock.scale = scale;
+        Ok(format!("Time scale {}", scale))
+    });
+    commands.register("step", "step [on|off]", |pong, args| {
+        let clock = &mut pong.clock;
+        match args.first() {
+            Some(&"on") => {
+                clock.stepping = true;
+                clock.steps = 0;
+            },
+            Some(&"off") => clock.stepping = false,
+            Some(mode) => return Err(format!("Unknown step mode {}", mode)),
+            None if clock.stepping => clock.steps += 1,
+            None => return Err(String::from("Step mode is off, enable it with step on"))
+        }
+        Ok(String::from(if clock.stepping { "Stepping" } else { "Running" }))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_stop_freezes_for_its_duration() {
+        let mut clock = Clock::default();
+        clock.hit_stop(40.0);
+        clock.hit_stop(20.0);
+        assert_eq!(clock.advance(16.0, 1.0), 0.0);
+        assert_eq!(clock.advance(16.0, 1.0), 0.0);
+        assert_eq!(clock.advance(16.0, 1.0), 0.0);
+        assert_eq!(clock.advance(16.0, 1.0), 16.0);
+    }
+
+    #[test]
+    fn step_mode_runs_one_frame_per_step() {
+        let mut clock = Clock { stepping: true, ..Clock::default() };
+        assert_eq!(clock.advance(16.0, 1.0), 0.0);
+        clock.steps = 2;
+        assert_eq!(clock.advance(100.0, 0.25), STEP_DELTA);
+        assert_eq!(clock.advance(100.0, 0.25), STEP_DELTA);
+        assert_eq!(clock.advance(100.0, 0.25), 0.0);
+        clock.stepping = false;
+        assert_eq!(clock.advance(16.0, 1.0), 16.0);
+    }
+
+    #[test]
+    fn scale_multiplies_frame_time() {
+        let mut clock = Clock { scale: 2.0, ..Clock::default() };
+        assert_eq!(clock.advance(16.0, 1.0), 32.0);
+        assert_eq!(clock.advance(16.0, 0.25), 8.0);
+        clock.stepping = true;
+        clock.steps = 1;
+        assert_eq!(clock.advance(16.0, 1.0), STEP_DELTA * 2.0);
+    }
+}
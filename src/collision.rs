@@ -0,0 +1,219 @@
+use serde::Deserialize;
+
+use crate::{clamp, Vec2};
+
+// Collision shapes centered on an entity's position. Capsules are vertical:
+// a segment of 2 * half_length along the y axis inflated by radius.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Shape {
+    Aabb { half_width: f32, half_height: f32 },
+    Circle { radius: f32 },
+    Capsule { half_length: f32, radius: f32 }
+}
+
+// Direction to move the first shape out of the second, and how far
+pub struct Contact {
+    pub normal: Vec2,
+    pub depth: f32
+}
+
+impl Shape {
+    // Half size of the shape's bounding box
+    pub fn extent(&self) -> Vec2 {
+        match *self {
+            Shape::Aabb { half_width, half_height } => Vec2::new(half_width, half_height),
+            Shape::Circle { radius } => Vec2::new(radius, radius),
+            Shape::Capsule { half_length, radius } => Vec2::new(radius, half_length + radius)
+        }
+    }
+
+    // The shape with its height multiplied by `factor`, keeping its width
+    pub fn stretched(&self, factor: f32) -> Shape {
+        match *self {
+            Shape::Aabb { half_width, half_height } => Shape::Aabb { half_width, half_height: half_height * factor },
+            Shape::Circle { radius } => Shape::Capsule { half_length: radius * (factor - 1.0).max(0.0), radius },
+            Shape::Capsule { half_length, radius } => Shape::Capsule {
+                half_length: ((half_length + radius) * factor - radius).max(0.0),
+                radius
+            }
+        }
+    }
+
+    // The shape turned on its side. Capsules can only be vertical, so they
+    // become boxes.
+    pub fn transposed(&self) -> Shape {
+        let extent = self.extent();
+        match *self {
+            Shape::Circle { .. } => *self,
+            _ => Shape::Aabb { half_width: extent.y, half_height: extent.x }
+        }
+    }
+
+    // Circles and capsules as a vertical core segment and a radius
+    fn rounded(&self) -> Option<(f32, f32)> {
+        match *self {
+            Shape::Aabb { .. } => None,
+            Shape::Circle { radius } => Some((0.0, radius)),
+            Shape::Capsule { half_length, radius } => Some((half_length, radius))
+        }
+    }
+}
+
+pub fn collide(p1: &Vec2, s1: &Shape, p2: &Vec2, s2: &Shape) -> Option<Contact> {
+    match (s1.rounded(), s2.rounded()) {
+        (None, None) => aabb_aabb(p1, &s1.extent(), p2, &s2.extent()),
+        (Some(r1), None) => rounded_aabb(p1, r1, p2, &s2.extent()),
+        (None, Some(r2)) => rounded_aabb(p2, r2, p1, &s1.extent()).map(|c| Contact {
+            normal: Vec2::new(-c.normal.x, -c.normal.y),
+            depth: c.depth
+        }),
+        (Some(r1), Some(r2)) => rounded_rounded(p1, r1, p2, r2)
+    }
+}
+
+// Collides a shape with a line segment from `a` to `b`, treating the shape as
+// rounded. Boxes are approximated by the circle of their half width.
+pub fn collide_segment(p: &Vec2, shape: &Shape, a: &Vec2, b: &Vec2) -> Option<Contact> {
+    let (half_length, radius) = shape.rounded().unwrap_or((0.0, shape.extent().x));
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 { 0.0 } else { clamp(((p.x - a.x) * dx + (p.y - a.y) * dy) / length, 0.0, 1.0) };
+    let closest = Vec2::new(a.x + dx * t, a.y + dy * t);
+    // The core point nearest to the segment's closest point
+    let center = Vec2::new(p.x, clamp(closest.y, p.y - half_length, p.y + half_length));
+    let (nx, ny) = (center.x - closest.x, center.y - closest.y);
+    let distance = (nx * nx + ny * ny).sqrt();
+
+    if distance >= radius {
+        None
+    } else if distance > 0.0 {
+        Some(Contact { normal: Vec2::new(nx / distance, ny / distance), depth: radius - distance })
+    } else {
+        let normal = if length == 0.0 { Vec2::new(0.0, 1.0) } else { Vec2::new(-dy, dx).normalized() };
+        Some(Contact { normal, depth: radius })
+    }
+}
+
+fn aabb_aabb(p1: &Vec2, e1: &Vec2, p2: &Vec2, e2: &Vec2) -> Option<Contact> {
+    let dx = p1.x - p2.x;
+    let dy = p1.y - p2.y;
+    let overlap_x = e1.x + e2.x - dx.abs();
+    let overlap_y = e1.y + e2.y - dy.abs();
+    if overlap_x <= 0.0 || overlap_y <= 0.0 {
+        None
+    } else if overlap_x < overlap_y {
+        Some(Contact { normal: Vec2::new(dx.signum(), 0.0), depth: overlap_x })
+    } else {
+        Some(Contact { normal: Vec2::new(0.0, dy.signum()), depth: overlap_y })
+    }
+}
+
+// The point of the core segment nearest to the box is tested as a circle
+// against the box's closest point, so corners and ends deflect along the
+// line between them
+fn rounded_aabb(p1: &Vec2, (half_length, radius): (f32, f32), p2: &Vec2, e2: &Vec2) -> Option<Contact> {
+    let center = Vec2::new(p1.x, clamp(p2.y, p1.y - half_length, p1.y + half_length));
+    let closest = Vec2::new(clamp(center.x, p2.x - e2.x, p2.x + e2.x),
+                            clamp(center.y, p2.y - e2.y, p2.y + e2.y));
+    let dx = center.x - closest.x;
+    let dy = center.y - closest.y;
+    let distance = (dx * dx + dy * dy).sqrt();
+
+    if distance >= radius {
+        None
+    } else if distance > 0.0 {
+        Some(Contact { normal: Vec2::new(dx / distance, dy / distance), depth: radius - distance })
+    } else {
+        // The core is inside the box, so push out through the nearest side
+        aabb_aabb(&center, &Vec2::zero(), p2, e2).map(|c| Contact {
+            normal: c.normal,
+            depth: c.depth + radius
+        })
+    }
+}
+
+fn rounded_rounded(p1: &Vec2, (l1, r1): (f32, f32), p2: &Vec2, (l2, r2): (f32, f32)) -> Option<Contact> {
+    let dx = p1.x - p2.x;
+    let gap = (p1.y - p2.y).abs() - l1 - l2;
+    let dy = if gap > 0.0 { gap * (p1.y - p2.y).signum() } else { 0.0 };
+    let distance = (dx * dx + dy * dy).sqrt();
+    let radius = r1 + r2;
+
+    if distance >= radius {
+        None
+    } else if distance > 0.0 {
+        Some(Contact { normal: Vec2::new(dx / distance, dy / distance), depth: radius - distance })
+    } else {
+        Some(Contact { normal: Vec2::new(0.0, 1.0), depth: radius })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BALL: Shape = Shape::Circle { radius: 0.05 };
+    const PADDLE: Shape = Shape::Aabb { half_width: 0.05, half_height: 0.2 };
+    const CAPSULE: Shape = Shape::Capsule { half_length: 0.15, radius: 0.05 };
+
+    fn assert_contact(contact: Option<Contact>, normal: (f32, f32), depth: f32) {
+        let contact = contact.expect("shapes should touch");
+        assert!((contact.normal.x - normal.0).abs() < 1e-4 && (contact.normal.y - normal.1).abs() < 1e-4,
+                "normal ({}, {}), expected {:?}", contact.normal.x, contact.normal.y, normal);
+        assert!((contact.depth - depth).abs() < 1e-4, "depth {}, expected {}", contact.depth, depth);
+    }
+
+    #[test]
+    fn ball_hits_paddle_face_along_an_axis() {
+        let paddle = Vec2::new(0.0, 0.0);
+        assert_contact(collide(&Vec2::new(0.09, 0.1), &BALL, &paddle, &PADDLE), (1.0, 0.0), 0.01);
+        assert_contact(collide(&Vec2::new(-0.08, -0.1), &BALL, &paddle, &PADDLE), (-1.0, 0.0), 0.02);
+        // The paddle is pushed out of the ball the opposite way
+        assert_contact(collide(&paddle, &PADDLE, &Vec2::new(0.09, 0.1), &BALL), (-1.0, 0.0), 0.01);
+    }
+
+    #[test]
+    fn paddle_corners_deflect_diagonally() {
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        let offset = 0.03;
+        let depth = 0.05 - offset * std::f32::consts::SQRT_2;
+        let ball = Vec2::new(0.05 + offset, 0.2 + offset);
+        assert_contact(collide(&ball, &BALL, &Vec2::zero(), &PADDLE), (diagonal, diagonal), depth);
+        let ball = Vec2::new(0.05 + offset, -0.2 - offset);
+        assert_contact(collide(&ball, &BALL, &Vec2::zero(), &PADDLE), (diagonal, -diagonal), depth);
+    }
+
+    #[test]
+    fn capsule_end_caps_deflect_diagonally() {
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        // The ball is beyond the end of the capsule's core segment
+        let ball = Vec2::new(0.06, 0.15 + 0.06);
+        let depth = 0.1 - 0.06 * std::f32::consts::SQRT_2;
+        assert_contact(collide(&ball, &BALL, &Vec2::zero(), &CAPSULE), (diagonal, diagonal), depth);
+        // Beside the core it is a straight face
+        assert_contact(collide(&Vec2::new(0.08, 0.1), &BALL, &Vec2::zero(), &CAPSULE), (1.0, 0.0), 0.02);
+    }
+
+    #[test]
+    fn separated_shapes_do_not_touch() {
+        assert!(collide(&Vec2::new(0.2, 0.0), &BALL, &Vec2::zero(), &PADDLE).is_none());
+        assert!(collide(&Vec2::new(0.1, 0.0), &BALL, &Vec2::zero(), &PADDLE).is_none());
+        assert!(collide(&Vec2::new(0.09, 0.29), &BALL, &Vec2::zero(), &PADDLE).is_none());
+        assert!(collide(&Vec2::new(0.0, 0.31), &BALL, &Vec2::zero(), &CAPSULE).is_none());
+    }
+
+    #[test]
+    fn segments_push_along_their_normal_or_from_their_ends() {
+        let (a, b) = (Vec2::new(-0.5, 0.95), Vec2::new(0.5, 0.95));
+        assert_contact(collide_segment(&Vec2::new(0.0, 0.92), &BALL, &a, &b), (0.0, -1.0), 0.02);
+        assert_contact(collide_segment(&Vec2::new(0.0, 0.98), &BALL, &a, &b), (0.0, 1.0), 0.02);
+        // Past the end the ball is pushed away from the end point
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        let ball = Vec2::new(0.52, 0.93);
+        let depth = 0.05 - 0.02 * std::f32::consts::SQRT_2;
+        assert_contact(collide_segment(&ball, &BALL, &a, &b), (diagonal, -diagonal), depth);
+        assert!(collide_segment(&Vec2::new(0.0, 0.85), &BALL, &a, &b).is_none());
+        assert!(collide_segment(&Vec2::new(0.6, 0.95), &BALL, &a, &b).is_none());
+    }
+}
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+// Runs a command with its arguments against the game, returning a message to
+// show to the user
+pub type Handler<T> = fn(&mut T, &[&str]) -> Result<String, String>;
+
+pub struct Command<T> {
+    pub name: &'static str,
+    // e.g. "score <left> <right>", optional arguments are in [brackets]
+    pub usage: &'static str,
+    pub run: Handler<T>
+}
+
+// Commands available in the developer console. Modules add their own with
+// `register`.
+pub struct Commands<T> {
+    commands: Vec<Command<T>>
+}
+
+impl<T> Clone for Command<T> {
+    fn clone(&self) -> Command<T> {
+        Command { name: self.name, usage: self.usage, run: self.run }
+    }
+}
+
+impl<T> Clone for Commands<T> {
+    fn clone(&self) -> Commands<T> {
+        Commands { commands: self.commands.clone() }
+    }
+}
+
+impl<T> Default for Commands<T> {
+    fn default() -> Commands<T> {
+        Commands { commands: Vec::new() }
+    }
+}
+
+impl<T> Commands<T> {
+    pub fn register(&mut self, name: &'static str, usage: &'static str, run: Handler<T>) {
+        self.commands.retain(|c| c.name != name);
+        self.commands.push(Command { name, usage, run });
+    }
+
+    pub fn execute(&self, target: &mut T, line: &str) -> Result<String, String> {
+        let words = parse(line)?;
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.as_str(), args),
+            None => return Ok(String::new())
+        };
+        if name == "help" {
+            return Ok(self.help());
+        }
+
+        let command = self.commands.iter().find(|c| c.name == name)
+            .ok_or_else(|| format!("Unknown command {}, try help", name))?;
+        let (required, optional) = arity(command.usage);
+        if args.len() < required || args.len() > required + optional {
+            return Err(format!("Usage: {}", command.usage));
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        (command.run)(target, &args)
+    }
+
+    fn help(&self) -> String {
+        let mut usages: Vec<&str> = self.commands.iter().map(|c| c.usage).collect();
+        usages.sort_unstable();
+        usages.join("\n")
+    }
+}
+
+// Splits a command line into words at whitespace. Double quotes group words
+// containing spaces.
+pub fn parse(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            },
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            },
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(String::from("Unterminated quote"));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// Parses an argument, naming it in the error message
+pub fn arg<A: FromStr>(value: &str, name: &str) -> Result<A, String> {
+    value.parse().map_err(|_| format!("Invalid {}: {}", name, value))
+}
+
+fn arity(usage: &str) -> (usize, usize) {
+    let words = usage.split_whitespace().skip(1);
+    words.fold((0, 0), |(required, optional), word| {
+        if word.starts_with('<') {
+            (required + 1, optional)
+        } else if word.starts_with('[') {
+            (required, optional + 1)
+        } else {
+            (required, optional)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{arg, parse, Commands};
+
+    #[derive(Default)]
+    struct Score {
+        left: u32,
+        right: u32
+    }
+
+    fn commands() -> Commands<Score> {
+        let mut commands: Commands<Score> = Commands::default();
+        commands.register("score", "score <left> <right>", |score, args| {
+            score.left = arg(args[0], "left score")?;
+            score.right = arg(args[1], "right score")?;
+            Ok(format!("{}-{}", score.left, score.right))
+        });
+        commands.register("reset", "reset [side]", |score, args| {
+            match args.first() {
+                Some(&"left") => score.left = 0,
+                Some(&"right") => score.right = 0,
+                Some(side) => return Err(format!("Unknown side {}", side)),
+                None => *score = Score::default()
+            }
+            Ok(String::new())
+        });
+        commands
+    }
+
+    #[test]
+    fn parses_words_and_quotes() {
+        assert_eq!(parse("  set ball_speed\t0.002 "), Ok(vec![
+            String::from("set"), String::from("ball_speed"), String::from("0.002")
+        ]));
+        assert_eq!(parse("say \"hello world\" \"\""), Ok(vec![
+            String::from("say"), String::from("hello world"), String::new()
+        ]));
+        assert_eq!(parse(""), Ok(Vec::new()));
+        assert!(parse("say \"hello").is_err());
+    }
+
+    #[test]
+    fn executes_registered_commands() {
+        let commands = commands();
+        let mut score = Score::default();
+
+        assert_eq!(commands.execute(&mut score, "score 5 3"), Ok(String::from("5-3")));
+        assert_eq!((score.left, score.right), (5, 3));
+        assert_eq!(commands.execute(&mut score, "reset left"), Ok(String::new()));
+        assert_eq!((score.left, score.right), (0, 3));
+        assert_eq!(commands.execute(&mut score, "reset"), Ok(String::new()));
+        assert_eq!((score.left, score.right), (0, 0));
+        assert_eq!(commands.execute(&mut score, ""), Ok(String::new()));
+
+        assert_eq!(commands.execute(&mut score, "score 5"), Err(String::from("Usage: score <left> <right>")));
+        assert_eq!(commands.execute(&mut score, "score 1 2 3"), Err(String::from("Usage: score <left> <right>")));
+        assert_eq!(commands.execute(&mut score, "score x 3"), Err(String::from("Invalid left score: x")));
+        assert!(commands.execute(&mut score, "spawn_ball").is_err());
+        assert_eq!(commands.execute(&mut score, "help"), Ok(String::from("reset [side]\nscore <left> <right>")));
+    }
+}
@@ -0,0 +1,438 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::error::PongError;
+
+const MAX_PARTICLES: usize = 10000;
+const MAX_BALLS: usize = 16;
+// Steepest launch angle in degrees, beyond which balls only bounce between the
+// walls
+const MAX_LAUNCH_ANGLE: f32 = 75.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Classic,
+    FourPlayer,
+    Circle,
+    Breakout,
+    Squash,
+    Practice,
+    Doubles
+}
+
+// Where the second paddle of each doubles team plays
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DoublesLayout {
+    // In front of the first paddle, at `doubles_depth` from the center
+    Depth,
+    // Beside the first paddle, each guarding half of the goal
+    Zones
+}
+
+// Which paddle plays breakout, with the bricks on the opposite side
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakoutPaddle {
+    Left,
+    Bottom
+}
+
+// Ball launcher feeding the player in practice mode
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Launcher {
+    // Degrees from straight at the player, positive upwards
+    pub angle: f32,
+    // Ball velocity, 1 being the speed of a straight serve
+    pub speed: f32,
+    // Sideways acceleration, as given by curve shots
+    pub spin: f32,
+    // Milliseconds between balls
+    pub interval: f32,
+    // Balls in a practice session
+    pub balls: u32
+}
+
+impl Default for Launcher {
+    fn default() -> Launcher {
+        Launcher { angle: 0.0, speed: 1.4, spin: 0.0, interval: 2000.0, balls: 20 }
+    }
+}
+
+// Physical paddle movement: inputs accelerate the paddle instead of moving it
+// at `paddle_speed`, friction slows it down and it bounces off the ends of its
+// track. Speeds are in field units per millisecond.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Inertia {
+    // Speed gained per millisecond while an input is held
+    pub acceleration: f32,
+    pub max_speed: f32,
+    // Share of its speed the paddle loses per millisecond
+    pub friction: f32,
+    // Share of its speed the paddle keeps when it bounces off a limit
+    pub bounce: f32,
+    // Share of the paddle's speed given to balls it hits
+    pub transfer: f32
+}
+
+impl Default for Inertia {
+    fn default() -> Inertia {
+        Inertia { acceleration: 0.00002, max_speed: 0.0015, friction: 0.01, bounce: 0.5, transfer: 0.5 }
+    }
+}
+
+// Paddles using the physical model, by the names the `ai` command uses. The
+// others move at a constant speed.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaddleInertia {
+    pub left: Option<Inertia>,
+    pub right: Option<Inertia>,
+    pub top: Option<Inertia>,
+    pub bottom: Option<Inertia>,
+    pub left2: Option<Inertia>,
+    pub right2: Option<Inertia>
+}
+
+impl PaddleInertia {
+    fn paddles(&self) -> [(&'static str, Option<Inertia>); 6] {
+        [("left", self.left), ("right", self.right), ("top", self.top),
+         ("bottom", self.bottom), ("left2", self.left2), ("right2", self.right2)]
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub mode: Mode,
+    pub paddle_speed: f32,
+    pub ball_speed: f32,
+    pub paddle_limit: f32,
+    pub paddle_x: f32,
+    pub wall: f32,
+    pub goal_line: f32,
+    pub winning_score: u32,
+    pub lives: u32,
+    pub breakout_paddle: BreakoutPaddle,
+    pub launcher: Launcher,
+    pub doubles_layout: DoublesLayout,
+    pub doubles_depth: f32,
+    pub spin: f32,
+    pub magnus: f32,
+    pub spin_decay: f32,
+    pub paddle_inertia: PaddleInertia,
+    pub ball_tail_particles: usize,
+    pub spark_particles: usize,
+    pub sparks_per_hit: usize,
+    pub rounded_paddles: bool,
+    pub hit_stop: f32,
+    pub slow_motion: f32,
+    pub replay: bool,
+    pub multiball: bool,
+    pub max_balls: usize,
+    pub ball_spawn_interval: f32,
+    pub powerups: bool,
+    pub powerup_interval: f32,
+    pub arena: String,
+    pub webgl2: bool
+}
+
+// Reads a value passed from JavaScript as either a JSON string or an object,
+// naming it `what` in errors
+pub fn from_js<T: DeserializeOwned>(value: JsValue, what: &str) -> Result<T, PongError> {
+    match value.as_string() {
+        Some(json) => from_json(&json, what),
+        None => serde_wasm_bindgen::from_value(value)
+            .map_err(|e| PongError::Config(format!("Invalid {}: {}", what, e)))
+    }
+}
+
+fn from_json<T: DeserializeOwned>(json: &str, what: &str) -> Result<T, PongError> {
+    serde_json::from_str(json).map_err(|e| PongError::Config(format!("Invalid {}: {}", what, e)))
+}
+
+// JSON pointer to the field named `name`
+fn pointer(name: &str) -> String {
+    format!("/{}", name.replace('.', "/"))
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            mode: Mode::Classic,
+            paddle_speed: 0.001,
+            ball_speed: 0.0012,
+            paddle_limit: 0.8,
+            paddle_x: 0.9,
+            wall: 0.95,
+            goal_line: 1.05,
+            winning_score: 11,
+            lives: 5,
+            breakout_paddle: BreakoutPaddle::Bottom,
+            launcher: Launcher::default(),
+            doubles_layout: DoublesLayout::Depth,
+            doubles_depth: 0.5,
+            spin: 0.5,
+            magnus: 0.03,
+            spin_decay: 1500.0,
+            paddle_inertia: PaddleInertia::default(),
+            ball_tail_particles: 100,
+            spark_particles: 100,
+            sparks_per_hit: 8,
+            rounded_paddles: false,
+            hit_stop: 40.0,
+            slow_motion: 0.25,
+            replay: true,
+            multiball: false,
+            max_balls: 3,
+            ball_spawn_interval: 10000.0,
+            powerups: false,
+            powerup_interval: 6000.0,
+            arena: String::from("classic"),
+            webgl2: true
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn from_js(value: JsValue) -> Result<GameConfig, PongError> {
+        let config: GameConfig = if value.is_undefined() || value.is_null() {
+            GameConfig::default()
+        } else {
+            from_js(value, "game configuration")?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Changes a single field by name, parsing `value` as JSON where possible.
+    // Fields of nested objects are named with dots, e.g. `launcher.angle`.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), PongError> {
+        let mut fields = serde_json::to_value(&*self)
+            .map_err(|e| PongError::Config(e.to_string()))?;
+        match fields.pointer_mut(&pointer(name)) {
+            Some(field) => *field = serde_json::from_str(value)
+                .unwrap_or_else(|_| serde_json::Value::String(String::from(value))),
+            None => return Err(PongError::Config(format!("Unknown setting {}", name)))
+        }
+        let config: GameConfig = serde_json::from_value(fields)
+            .map_err(|e| PongError::Config(format!("Invalid value for {}: {}", name, e)))?;
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        serde_json::to_value(self).ok()?.pointer(&pointer(name)).map(|value| value.to_string())
+    }
+
+    pub fn validate(&self) -> Result<(), PongError> {
+        let mut errors = Vec::new();
+        let mut positive = |name: &str, value: f32| {
+            if !(value.is_finite() && value > 0.0) {
+                errors.push(format!("{} must be a positive number, got {}", name, value));
+            }
+        };
+        positive("paddle_speed", self.paddle_speed);
+        positive("ball_speed", self.ball_speed);
+        positive("paddle_limit", self.paddle_limit);
+        positive("paddle_x", self.paddle_x);
+        positive("wall", self.wall);
+        positive("goal_line", self.goal_line);
+        positive("ball_spawn_interval", self.ball_spawn_interval);
+        positive("powerup_interval", self.powerup_interval);
+        positive("doubles_depth", self.doubles_depth);
+        positive("launcher.speed", self.launcher.speed);
+        positive("launcher.interval", self.launcher.interval);
+        positive("spin_decay", self.spin_decay);
+
+        if !(self.hit_stop.is_finite() && self.hit_stop >= 0.0) {
+            errors.push(format!("hit_stop must be zero or positive, got {}", self.hit_stop));
+        }
+        for (name, value) in [("spin", self.spin), ("magnus", self.magnus)] {
+            if !(value.is_finite() && value >= 0.0) {
+                errors.push(format!("{} must be zero or positive, got {}", name, value));
+            }
+        }
+        for (paddle, inertia) in self.paddle_inertia.paddles() {
+            let inertia = match inertia {
+                Some(inertia) => inertia,
+                None => continue
+            };
+            for (name, value) in [("acceleration", inertia.acceleration), ("max_speed", inertia.max_speed)] {
+                if !(value.is_finite() && value > 0.0) {
+                    errors.push(format!("paddle_inertia.{}.{} must be a positive number, got {}",
+                                        paddle, name, value));
+                }
+            }
+            for (name, value) in [("friction", inertia.friction), ("bounce", inertia.bounce),
+                                  ("transfer", inertia.transfer)] {
+                if !(0.0..=1.0).contains(&value) {
+                    errors.push(format!("paddle_inertia.{}.{} must be between 0 and 1, got {}",
+                                        paddle, name, value));
+                }
+            }
+        }
+        if !(self.slow_motion > 0.0 && self.slow_motion <= 1.0) {
+            errors.push(format!("slow_motion must be above 0 and at most 1, got {}", self.slow_motion));
+        }
+        if self.paddle_limit > 1.0 {
+            errors.push(format!("paddle_limit must be at most 1.0, got {}", self.paddle_limit));
+        }
+        if self.wall > 1.0 {
+            errors.push(format!("wall must be at most 1.0, got {}", self.wall));
+        }
+        if self.goal_line <= self.paddle_x {
+            errors.push(format!("goal_line ({}) must be beyond paddle_x ({})",
+                                self.goal_line, self.paddle_x));
+        }
+        if self.goal_line <= self.wall {
+            errors.push(format!("goal_line ({}) must be beyond wall ({})", self.goal_line, self.wall));
+        }
+        if self.doubles_depth >= self.paddle_x {
+            errors.push(format!("doubles_depth ({}) must be less than paddle_x ({})",
+                                self.doubles_depth, self.paddle_x));
+        }
+        if self.max_balls == 0 || self.max_balls > MAX_BALLS {
+            errors.push(format!("max_balls must be between 1 and {}, got {}", MAX_BALLS, self.max_balls));
+        }
+        if self.winning_score == 0 {
+            errors.push(String::from("winning_score must be at least 1"));
+        }
+        if self.lives == 0 {
+            errors.push(String::from("lives must be at least 1"));
+        }
+        if !(self.launcher.angle.is_finite() && self.launcher.angle.abs() <= MAX_LAUNCH_ANGLE) {
+            errors.push(format!("launcher.angle must be between -{} and {}, got {}",
+                                MAX_LAUNCH_ANGLE, MAX_LAUNCH_ANGLE, self.launcher.angle));
+        }
+        if !self.launcher.spin.is_finite() {
+            errors.push(format!("launcher.spin must be a number, got {}", self.launcher.spin));
+        }
+        if self.launcher.balls == 0 {
+            errors.push(String::from("launcher.balls must be at least 1"));
+        }
+        for (name, count) in [("ball_tail_particles", self.ball_tail_particles),
+                              ("spark_particles", self.spark_particles),
+                              ("sparks_per_hit", self.sparks_per_hit)] {
+            if count > MAX_PARTICLES {
+                errors.push(format!("{} must be at most {}, got {}", name, MAX_PARTICLES, count));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(PongError::Config(format!("Invalid game configuration: {}", errors.join("; "))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(config: &GameConfig) -> String {
+        match config.validate() {
+            Ok(()) => panic!("Configuration was accepted"),
+            Err(e) => String::from(e.message())
+        }
+    }
+
+    fn rejected(change: impl Fn(&mut GameConfig)) -> String {
+        let mut config = GameConfig::default();
+        change(&mut config);
+        error(&config).replacen("Invalid game configuration: ", "", 1)
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(GameConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_values_are_rejected_with_their_names() {
+        assert_eq!(rejected(|c| c.goal_line = c.wall), "goal_line (0.95) must be beyond wall (0.95)");
+        assert_eq!(rejected(|c| c.paddle_x = 1.1), "goal_line (1.05) must be beyond paddle_x (1.1)");
+        assert_eq!(rejected(|c| c.paddle_limit = 1.5), "paddle_limit must be at most 1.0, got 1.5");
+        assert_eq!(rejected(|c| c.paddle_limit = -0.1), "paddle_limit must be a positive number, got -0.1");
+        assert_eq!(rejected(|c| c.winning_score = 0), "winning_score must be at least 1");
+        assert_eq!(rejected(|c| c.launcher.angle = 80.0), "launcher.angle must be between -75 and 75, got 80");
+        assert_eq!(rejected(|c| c.paddle_inertia.left2 = Some(Inertia { friction: 2.0, ..Inertia::default() })),
+                   "paddle_inertia.left2.friction must be between 0 and 1, got 2");
+    }
+
+    #[test]
+    fn all_errors_are_reported_together() {
+        let config = GameConfig { lives: 0, max_balls: 0, ..GameConfig::default() };
+        assert_eq!(error(&config), "Invalid game configuration: \
+                                    max_balls must be between 1 and 16, got 0; lives must be at least 1");
+    }
+
+    #[test]
+    fn json_strings_fill_in_defaults() {
+        let config: GameConfig = from_json(r#"{"mode": "breakout", "winning_score": 5, "launcher": {"angle": 30}}"#,
+                                           "game configuration").unwrap();
+        assert!(config.mode == Mode::Breakout);
+        assert_eq!(config.winning_score, 5);
+        assert_eq!(config.launcher.angle, 30.0);
+        assert_eq!(config.launcher.speed, Launcher::default().speed);
+        assert_eq!(config.ball_speed, GameConfig::default().ball_speed);
+    }
+
+    #[test]
+    fn json_strings_and_objects_parse_alike() {
+        let json = r#"{"ball_speed": 0.002, "paddle_inertia": {"left": {"bounce": 0.2}}}"#;
+        let from_string: GameConfig = from_json(json, "game configuration").unwrap();
+        let from_object = GameConfig::deserialize(serde_json::from_str::<serde_json::Value>(json).unwrap()).unwrap();
+        assert_eq!(serde_json::to_value(&from_string).unwrap(), serde_json::to_value(&from_object).unwrap());
+        assert_eq!(from_string.paddle_inertia.left.map(|i| i.bounce), Some(0.2));
+    }
+
+    #[test]
+    fn unknown_fields_and_bad_types_are_rejected() {
+        let message = |json| match from_json::<GameConfig>(json, "game configuration") {
+            Ok(_) => panic!("{} was accepted", json),
+            Err(e) => String::from(e.message())
+        };
+        assert!(message(r#"{"bal_speed": 0.002}"#).starts_with("Invalid game configuration: unknown field `bal_speed`"));
+        assert!(message(r#"{"launcher": {"angel": 10}}"#).starts_with("Invalid game configuration: unknown field `angel`"));
+        assert!(message(r#"{"winning_score": "five"}"#).starts_with("Invalid game configuration: invalid type"));
+        assert!(message(r#"{"mode": "tennis"}"#).starts_with("Invalid game configuration: unknown variant `tennis`"));
+    }
+
+    #[test]
+    fn settings_are_changed_by_name() {
+        let mut config = GameConfig::default();
+        config.set("ball_speed", "0.002").unwrap();
+        config.set("launcher.angle", "30").unwrap();
+        config.set("mode", "squash").unwrap();
+        config.set("paddle_inertia.right", r#"{"bounce": 0.1}"#).unwrap();
+
+        assert_eq!(config.ball_speed, 0.002);
+        assert_eq!(config.launcher.angle, 30.0);
+        assert!(config.mode == Mode::Squash);
+        assert_eq!(config.paddle_inertia.right.map(|i| i.bounce), Some(0.1));
+        assert_eq!(config.get("launcher.angle").as_deref(), Some("30.0"));
+        assert_eq!(config.get("mode").as_deref(), Some("\"squash\""));
+    }
+
+    #[test]
+    fn unknown_and_invalid_settings_leave_the_config_unchanged() {
+        let mut config = GameConfig::default();
+        let message = |result: Result<(), PongError>| String::from(result.unwrap_err().message());
+
+        assert_eq!(message(config.set("speed", "1")), "Unknown setting speed");
+        assert_eq!(message(config.set("launcher.range", "1")), "Unknown setting launcher.range");
+        assert!(message(config.set("winning_score", "many")).starts_with("Invalid value for winning_score"));
+        assert_eq!(message(config.set("winning_score", "0")),
+                   "Invalid game configuration: winning_score must be at least 1");
+        assert_eq!(config.winning_score, 11);
+        assert_eq!(config.get("speed"), None);
+        assert_eq!(config.get("launcher.range"), None);
+    }
+}
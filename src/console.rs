@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use web_sys::WebGlTexture;
+
+use crate::{Pong, RenderContext, GL};
+use crate::command::Commands;
+use crate::error::PongError;
+use crate::gl::VertexArray;
+use crate::text::TextRenderer;
+
+const OUTPUT_LINES: usize = 8;
+const TEXT_SIZE: f32 = 0.04;
+const LINE_HEIGHT: f32 = 0.06;
+const LEFT: f32 = -0.95;
+const TOP: f32 = 0.97;
+const PANEL_BOTTOM: f32 = 0.4;
+
+const PANEL_VERTICES: [f32; 2*3*4] = [
+    -1.0,PANEL_BOTTOM,0.0,0.0, 1.0,1.0,1.0,1.0, -1.0,1.0,0.0,1.0,
+    -1.0,PANEL_BOTTOM,0.0,0.0, 1.0,PANEL_BOTTOM,1.0,0.0, 1.0,1.0,1.0,1.0
+];
+const PANEL_TEXTURE: [u8; 4] = [0x00, 0x00, 0x20, 0xDD];
+
+// Drop-down developer console running commands from a registry that game
+// modules register into
+pub struct Console {
+    pub open: bool,
+    input: String,
+    last_input: String,
+    output: VecDeque<String>,
+    commands: Commands<Pong>,
+    graphics: Graphics
+}
+
+struct Graphics {
+    text: TextRenderer,
+    panel: VertexArray,
+    texture: WebGlTexture
+}
+
+impl Console {
+    pub fn new(ctx: &RenderContext, commands: Commands<Pong>) -> Result<Console, PongError> {
+        Ok(Console {
+            open: false,
+            input: String::new(),
+            last_input: String::new(),
+            output: VecDeque::with_capacity(OUTPUT_LINES),
+            commands,
+            graphics: Graphics::new(ctx)?
+        })
+    }
+
+    pub fn reload(&mut self, ctx: &RenderContext) -> Result<(), PongError> {
+        self.graphics = Graphics::new(ctx)?;
+        Ok(())
+    }
+
+    fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.output.len() == OUTPUT_LINES {
+                self.output.pop_front();
+            }
+            self.output.push_back(String::from(line));
+        }
+    }
+
+    pub fn render(&self, ctx: &RenderContext) {
+        let graphics = &self.graphics;
+        graphics.panel.bind(ctx);
+        ctx.gl.active_texture(GL::TEXTURE0);
+        ctx.gl.bind_texture(GL::TEXTURE_2D, Some(&graphics.texture));
+        ctx.gl.uniform1i(Some(&ctx.sampler), 0);
+        ctx.gl.uniform1f(Some(&ctx.opacity), 1.0);
+        ctx.gl.uniform4f(Some(&ctx.offset), 0.0, 0.0, 0.0, 0.0);
+        ctx.draw(PANEL_VERTICES.len() as i32 / 4);
+
+        for (i, line) in self.output.iter().enumerate() {
+            graphics.text.render(ctx, line, LEFT, TOP - i as f32 * LINE_HEIGHT, TEXT_SIZE, 0.8);
+        }
+        let prompt = format!("> {}_", self.input);
+        graphics.text.render(ctx, &prompt, LEFT, TOP - OUTPUT_LINES as f32 * LINE_HEIGHT, TEXT_SIZE, 1.0);
+    }
+}
+
+impl Graphics {
+    fn new(ctx: &RenderContext) -> Result<Graphics, PongError> {
+        let panel = VertexArray::new(ctx)?;
+        panel.upload(ctx, &PANEL_VERTICES, GL::STATIC_DRAW);
+        Ok(Graphics {
+            text: TextRenderer::new(ctx)?,
+            panel,
+            texture: ctx.load_texture(&PANEL_TEXTURE, 1, 1)?
+        })
+    }
+}
+
+// Handles a key pressed while the console is open
+pub fn on_key(pong: &mut Pong, key: &str) {
+    let console = &mut pong.console;
+    match key {
+        "Enter" => {
+            let line = std::mem::take(&mut console.input);
+            if line.trim().is_empty() {
+                return;
+            }
+            console.print(&format!("> {}", line));
+            // Handlers get the whole game, so they run on a copy of the registry
+            let commands = console.commands.clone();
+            let result = commands.execute(pong, &line);
+            let console = &mut pong.console;
+            match result {
+                Ok(message) => console.print(&message),
+                Err(message) => console.print(&format!("Error: {}", message))
+            }
+            console.last_input = line;
+        },
+        "Backspace" => {
+            console.input.pop();
+        },
+        "ArrowUp" => console.input = console.last_input.clone(),
+        "Escape" => console.open = false,
+        key if key.chars().count() == 1 => console.input.push_str(key),
+        _ => ()
+    }
+}
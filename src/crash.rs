@@ -0,0 +1,193 @@
+use std::cell::{Cell, RefCell};
+use std::panic::{self, PanicHookInfo};
+use std::sync::Once;
+use js_sys::{Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+use web_sys::HtmlCanvasElement;
+
+use crate::RenderContext;
+use crate::text::TextRenderer;
+
+const LINE_LENGTH: usize = 32;
+const TEXT_SIZE: f32 = 0.06;
+const LINE_HEIGHT: f32 = 0.1;
+
+// Everything needed to show the crash screen on a game's canvas without
+// touching the game state, which is left borrowed by the panicking code
+pub struct CrashScreen {
+    pub canvas: HtmlCanvasElement,
+    pub ctx: RenderContext,
+    pub text: TextRenderer
+}
+
+struct Crash {
+    message: String,
+    location: String,
+    activity: &'static str,
+    stack: String
+}
+
+thread_local! {
+    static CRASHED: Cell<bool> = const { Cell::new(false) };
+    static ACTIVITY: Cell<&'static str> = const { Cell::new("startup") };
+    static SCREENS: RefCell<Vec<(u32, CrashScreen)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_ID: Cell<u32> = const { Cell::new(0) };
+    static HANDLER: RefCell<Option<Function>> = const { RefCell::new(None) };
+}
+
+static INSTALL: Once = Once::new();
+
+/// Sets a callback receiving `{ message, location, activity, stack }` when
+/// the game module panics. After a panic all games stop and show an error.
+#[wasm_bindgen]
+pub fn set_crash_handler(callback: Option<Function>) {
+    install();
+    HANDLER.with(|handler| *handler.borrow_mut() = callback);
+}
+
+pub fn install() {
+    INSTALL.call_once(|| panic::set_hook(Box::new(on_panic)));
+}
+
+pub fn crashed() -> bool {
+    CRASHED.with(|crashed| crashed.get())
+}
+
+// Runs `f` unless the module has crashed, recording `activity` for crash reports
+pub fn guard<T>(activity: &'static str, f: impl FnOnce() -> T) -> Option<T> {
+    if crashed() {
+        return None;
+    }
+    ACTIVITY.with(|a| a.set(activity));
+    let result = f();
+    ACTIVITY.with(|a| a.set("idle"));
+    Some(result)
+}
+
+pub fn register(screen: CrashScreen) -> u32 {
+    let id = NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    });
+    SCREENS.with(|screens| screens.borrow_mut().push((id, screen)));
+    id
+}
+
+pub fn unregister(id: u32) {
+    SCREENS.with(|screens| {
+        if let Ok(mut screens) = screens.try_borrow_mut() {
+            screens.retain(|(i, _)| *i != id);
+        }
+    });
+}
+
+fn on_panic(info: &PanicHookInfo) {
+    CRASHED.with(|crashed| crashed.set(true));
+
+    let payload = info.payload();
+    let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("Unknown panic"));
+    let location = info.location()
+        .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+        .unwrap_or_default();
+    let stack = Reflect::get(&js_sys::Error::new(""), &"stack".into()).ok()
+        .and_then(|stack| stack.as_string())
+        .unwrap_or_default();
+    let crash = Crash { message, location, activity: ACTIVITY.with(|a| a.get()), stack };
+
+    gloo::console::error!(format!("Pong crashed during {}: {}\n    at {}\n\n{}",
+                                  crash.activity, crash.message, crash.location, crash.stack));
+
+    SCREENS.with(|screens| {
+        if let Ok(screens) = screens.try_borrow() {
+            for (_, screen) in screens.iter() {
+                screen.show(&crash);
+            }
+        }
+    });
+
+    HANDLER.with(|handler| {
+        if let Some(handler) = handler.try_borrow().ok().as_deref().and_then(Option::as_ref) {
+            let _ = handler.call1(&JsValue::NULL, &crash.to_js());
+        }
+    });
+}
+
+impl CrashScreen {
+    fn show(&self, crash: &Crash) {
+        if self.ctx.gl.is_context_lost() {
+            self.show_overlay(crash);
+        } else {
+            self.render(crash);
+        }
+    }
+
+    fn render(&self, crash: &Crash) {
+        self.ctx.gl.clear_color(0.3, 0.0, 0.0, 1.0);
+        self.ctx.begin_frame();
+
+        let mut lines = vec![String::from("The game crashed"), String::new()];
+        lines.extend(wrap(&crash.message));
+        lines.push(String::new());
+        lines.extend(wrap(&format!("During {}", crash.activity)));
+        lines.extend(wrap(&format!("At {}", crash.location)));
+
+        let x = -TextRenderer::width(&lines[0], TEXT_SIZE) / 2.0;
+        for (i, line) in lines.iter().enumerate() {
+            let x = if i == 0 { x } else { -0.9 };
+            self.text.render(&self.ctx, line, x, 0.8 - i as f32 * LINE_HEIGHT, TEXT_SIZE, 1.0);
+        }
+    }
+
+    // Shown next to the canvas when WebGL itself is unusable
+    fn show_overlay(&self, crash: &Crash) {
+        let document = match self.canvas.owner_document() {
+            Some(document) => document,
+            None => return
+        };
+        if let Ok(overlay) = document.create_element("pre") {
+            let _ = overlay.set_attribute("style", &format!(
+                "width:{}px;margin:0;padding:8px;box-sizing:border-box;\
+                 white-space:pre-wrap;background:#400;color:#fff;font:12px monospace",
+                self.canvas.width()));
+            overlay.set_text_content(Some(&format!("The game crashed\n\n{}\n\nDuring {}\nAt {}",
+                                                   crash.message, crash.activity, crash.location)));
+            let _ = self.canvas.after_with_node_1(&overlay);
+        }
+    }
+}
+
+impl Crash {
+    fn to_js(&self) -> JsValue {
+        let crash = Object::new();
+        let _ = Reflect::set(&crash, &"message".into(), &self.message.as_str().into());
+        let _ = Reflect::set(&crash, &"location".into(), &self.location.as_str().into());
+        let _ = Reflect::set(&crash, &"activity".into(), &self.activity.into());
+        let _ = Reflect::set(&crash, &"stack".into(), &self.stack.as_str().into());
+        crash.into()
+    }
+}
+
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line: Vec<char> = Vec::new();
+    for word in text.split_whitespace() {
+        let word: Vec<char> = word.chars().collect();
+        if !line.is_empty() && line.len() + 1 + word.len() > LINE_LENGTH {
+            lines.push(line.drain(..).collect());
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+        while line.len() > LINE_LENGTH {
+            lines.push(line.drain(..LINE_LENGTH).collect());
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line.into_iter().collect());
+    }
+    lines
+}
@@ -2,9 +2,14 @@ pub const VERTEX_SHADER: &str =
 "attribute vec2 a_position;
 attribute vec2 a_texcoord;
 uniform vec4 u_offset;
+uniform vec2 u_size;
+uniform vec2 u_rotation;
+uniform vec2 u_scale;
 varying mediump vec2 v_texcoord;
 void main() {
-  gl_Position = vec4(a_position, 1.0, 1.0) + u_offset;
+  mat2 rotation = mat2(u_rotation.x, u_rotation.y, -u_rotation.y, u_rotation.x);
+  vec4 position = vec4(rotation * (a_position * u_size), 1.0, 1.0) + u_offset;
+  gl_Position = vec4(position.xy * u_scale, position.zw);
   v_texcoord = a_texcoord;
 }";
 
@@ -19,6 +24,38 @@ void main() {
  gl_FragColor = color;
 }";
 
+// GLSL ES 3.00 versions for WebGL 2, taking per-frame values from a uniform block
+pub const VERTEX_SHADER_300: &str =
+"#version 300 es
+layout(std140) uniform Frame {
+  vec2 u_scale;
+};
+in vec2 a_position;
+in vec2 a_texcoord;
+uniform vec4 u_offset;
+uniform vec2 u_size;
+uniform vec2 u_rotation;
+out mediump vec2 v_texcoord;
+void main() {
+  mat2 rotation = mat2(u_rotation.x, u_rotation.y, -u_rotation.y, u_rotation.x);
+  vec4 position = vec4(rotation * (a_position * u_size), 1.0, 1.0) + u_offset;
+  gl_Position = vec4(position.xy * u_scale, position.zw);
+  v_texcoord = a_texcoord;
+}";
+
+pub const FRAGMENT_SHADER_300: &str =
+"#version 300 es
+precision mediump float;
+in mediump vec2 v_texcoord;
+uniform sampler2D u_sampler;
+uniform float u_opacity;
+out vec4 frag_color;
+void main() {
+ vec4 color = texture(u_sampler, v_texcoord);
+ color.a *= u_opacity;
+ frag_color = color;
+}";
+
 pub const BALL_VERTICES: [f32; 2*3*4] = [
     -0.05,-0.05,0.0,0.0, 0.05,0.05,1.0,1.0, -0.05,0.05,0.0,1.0,
     -0.05,-0.05,0.0,0.0, 0.05,-0.05,1.0,0.0, 0.05,0.05,1.0,1.0
@@ -86,3 +123,168 @@ pub const SPARK_TEXTURE: [u8; 4*4*4] = [
     0x00,0x00,0x00,0x00, 0xFF,0xFF,0x00,0xFF, 0xFF,0xFF,0x00,0xFF, 0x00,0x00,0x00,0x00,
 ];
 
+pub const OVERLAY_VERTICES: [f32; 2*3*4] = FIELD_VERTICES;
+
+pub const OVERLAY_TEXTURE: [u8; 4] = [0x00,0x00,0x00,0xAA];
+
+pub const PAUSE_VERTICES: [f32; 4*3*4] = [
+    -0.12,-0.15,0.0,0.0, -0.04,0.15,1.0,1.0, -0.12,0.15,0.0,1.0,
+    -0.12,-0.15,0.0,0.0, -0.04,-0.15,1.0,0.0, -0.04,0.15,1.0,1.0,
+     0.04,-0.15,0.0,0.0,  0.12,0.15,1.0,1.0,  0.04,0.15,0.0,1.0,
+     0.04,-0.15,0.0,0.0,  0.12,-0.15,1.0,0.0,  0.12,0.15,1.0,1.0
+];
+
+pub const PAUSE_TEXTURE: [u8; 4] = [0xEE,0xEE,0xEE,0xFF];
+
+pub const PICKUP_VERTICES: [f32; 2*3*4] = [
+    -0.04,-0.04,0.0,0.0, 0.04,0.04,1.0,1.0, -0.04,0.04,0.0,1.0,
+    -0.04,-0.04,0.0,0.0, 0.04,-0.04,1.0,0.0, 0.04,0.04,1.0,1.0
+];
+
+// Spans the full height when scaled by the wall distance, sampling the middle
+// of a pickup texture
+pub const SHIELD_VERTICES: [f32; 2*3*4] = [
+    -0.01,-1.0,0.5,0.5, 0.01,1.0,0.5,0.5, -0.01,1.0,0.5,0.5,
+    -0.01,-1.0,0.5,0.5, 0.01,-1.0,0.5,0.5, 0.01,1.0,0.5,0.5
+];
+
+// Built-in arenas, in the format accepted by PongGame.load_arena
+pub const ARENAS: &str = r#"[
+  { "name": "classic" },
+  { "name": "pillars",
+    "obstacles": [
+      { "position": [-0.4, 0.35], "shape": { "circle": { "radius": 0.08 } } },
+      { "position": [0.4, -0.35], "shape": { "circle": { "radius": 0.08 } } }
+    ] },
+  { "name": "bumpers",
+    "obstacles": [
+      { "position": [-0.45, 0.5], "shape": { "circle": { "radius": 0.07 } }, "restitution": 1.2 },
+      { "position": [0.45, 0.5], "shape": { "circle": { "radius": 0.07 } }, "restitution": 1.2 },
+      { "position": [-0.45, -0.5], "shape": { "circle": { "radius": 0.07 } }, "restitution": 1.2 },
+      { "position": [0.45, -0.5], "shape": { "circle": { "radius": 0.07 } }, "restitution": 1.2 }
+    ] },
+  { "name": "gates",
+    "obstacles": [
+      { "position": [-0.45, 0.0], "shape": { "aabb": { "half_width": 0.03, "half_height": 0.15 } },
+        "motion": { "offset": [0.0, 0.6], "period": 4000 } },
+      { "position": [0.45, 0.0], "shape": { "aabb": { "half_width": 0.03, "half_height": 0.15 } },
+        "motion": { "offset": [0.0, 0.6], "period": 4000, "phase": 0.5 } }
+    ] },
+  { "name": "narrow", "goal_width": 0.9,
+    "walls": [
+      { "from": [-1.0, 0.6], "to": [-0.7, 0.95] },
+      { "from": [0.7, 0.95], "to": [1.0, 0.6] },
+      { "from": [-1.0, -0.6], "to": [-0.7, -0.95] },
+      { "from": [0.7, -0.95], "to": [1.0, -0.6] }
+    ] }
+]"#;
+
+// Built-in breakout levels, in the format accepted by PongGame.add_level. Each
+// digit is a brick taking that many hits, dots are gaps.
+pub const LEVELS: &str = r#"[
+  { "name": "wall",
+    "rows": ["1111111111", "1111111111", "1111111111", "1111111111"] },
+  { "name": "stripes",
+    "rows": ["3333333333", "1111111111", "2222222222", "1111111111", "2222222222"] },
+  { "name": "checkers",
+    "rows": ["2.2.2.2.2.", ".1.1.1.1.1", "2.2.2.2.2.", ".1.1.1.1.1", "3.3.3.3.3."] },
+  { "name": "fortress",
+    "rows": ["..333333..", ".32222223.", "3211111123", "321....123", "3211111123", ".32222223."] }
+]"#;
+
+// Built-in power-ups, in the format accepted by PongGame.add_powerup
+pub const POWERUPS: &str = r#"[
+  { "name": "big_paddle", "duration": 10000, "color": [0, 200, 80], "paddle_scale": 1.5 },
+  { "name": "small_paddle", "duration": 8000, "target": "opponent", "color": [200, 60, 200],
+    "paddle_scale": 0.6 },
+  { "name": "fast_ball", "duration": 6000, "color": [255, 140, 0], "ball_speed": 1.4 },
+  { "name": "curve", "duration": 10000, "color": [0, 160, 255], "curve": 0.001 },
+  { "name": "shield", "duration": 6000, "color": [120, 220, 255], "shield": true },
+  { "name": "reverse", "duration": 5000, "target": "opponent", "color": [255, 60, 60],
+    "reverse_controls": true },
+  { "name": "split", "color": [255, 230, 0], "split_ball": true }
+]"#;
+
+// Background music patterns, one MIDI note per sixteenth step, 0 for rest
+pub const MUSIC_BASS: [u8; 32] = [
+    45,0,45,0, 45,0,57,0, 43,0,43,0, 43,0,55,0,
+    41,0,41,0, 41,0,53,0, 40,0,40,0, 40,0,52,0
+];
+
+pub const MUSIC_ARPEGGIO: [u8; 32] = [
+    69,72,76,72, 69,72,76,72, 67,71,74,71, 67,71,74,71,
+    65,69,72,69, 65,69,72,69, 64,68,71,68, 64,68,71,68
+];
+
+pub const MUSIC_LEAD: [u8; 32] = [
+    81,0,0,79, 0,0,76,0, 79,0,0,76, 0,0,74,0,
+    77,0,0,76, 0,0,72,0, 76,0,0,0, 80,0,0,0
+];
+
+// 5x7 bitmap font for ASCII 32-95, one byte per row from the top, bit 4 leftmost
+pub const FONT: [[u8; 7]; 64] = [
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00], // ' '
+    [0x04,0x04,0x04,0x04,0x04,0x00,0x04], // '!'
+    [0x0A,0x0A,0x00,0x00,0x00,0x00,0x00], // '"'
+    [0x0A,0x0A,0x1F,0x0A,0x1F,0x0A,0x0A], // '#'
+    [0x04,0x0F,0x14,0x0E,0x05,0x1E,0x04], // '$'
+    [0x18,0x19,0x02,0x04,0x08,0x13,0x03], // '%'
+    [0x0C,0x12,0x14,0x08,0x15,0x12,0x0D], // '&'
+    [0x04,0x04,0x00,0x00,0x00,0x00,0x00], // "'"
+    [0x02,0x04,0x08,0x08,0x08,0x04,0x02], // '('
+    [0x08,0x04,0x02,0x02,0x02,0x04,0x08], // ')'
+    [0x00,0x04,0x15,0x0E,0x15,0x04,0x00], // '*'
+    [0x00,0x04,0x04,0x1F,0x04,0x04,0x00], // '+'
+    [0x00,0x00,0x00,0x00,0x06,0x02,0x04], // ','
+    [0x00,0x00,0x00,0x1F,0x00,0x00,0x00], // '-'
+    [0x00,0x00,0x00,0x00,0x00,0x0C,0x0C], // '.'
+    [0x00,0x01,0x02,0x04,0x08,0x10,0x00], // '/'
+    [0x0E,0x11,0x13,0x15,0x19,0x11,0x0E], // '0'
+    [0x04,0x0C,0x04,0x04,0x04,0x04,0x0E], // '1'
+    [0x0E,0x11,0x01,0x02,0x04,0x08,0x1F], // '2'
+    [0x1F,0x02,0x04,0x02,0x01,0x11,0x0E], // '3'
+    [0x02,0x06,0x0A,0x12,0x1F,0x02,0x02], // '4'
+    [0x1F,0x10,0x1E,0x01,0x01,0x11,0x0E], // '5'
+    [0x06,0x08,0x10,0x1E,0x11,0x11,0x0E], // '6'
+    [0x1F,0x01,0x02,0x04,0x08,0x08,0x08], // '7'
+    [0x0E,0x11,0x11,0x0E,0x11,0x11,0x0E], // '8'
+    [0x0E,0x11,0x11,0x0F,0x01,0x02,0x0C], // '9'
+    [0x00,0x0C,0x0C,0x00,0x0C,0x0C,0x00], // ':'
+    [0x00,0x0C,0x0C,0x00,0x0C,0x04,0x08], // ';'
+    [0x02,0x04,0x08,0x10,0x08,0x04,0x02], // '<'
+    [0x00,0x00,0x1F,0x00,0x1F,0x00,0x00], // '='
+    [0x08,0x04,0x02,0x01,0x02,0x04,0x08], // '>'
+    [0x0E,0x11,0x01,0x02,0x04,0x00,0x04], // '?'
+    [0x0E,0x11,0x01,0x0D,0x15,0x15,0x0E], // '@'
+    [0x0E,0x11,0x11,0x1F,0x11,0x11,0x11], // 'A'
+    [0x1E,0x11,0x11,0x1E,0x11,0x11,0x1E], // 'B'
+    [0x0E,0x11,0x10,0x10,0x10,0x11,0x0E], // 'C'
+    [0x1C,0x12,0x11,0x11,0x11,0x12,0x1C], // 'D'
+    [0x1F,0x10,0x10,0x1E,0x10,0x10,0x1F], // 'E'
+    [0x1F,0x10,0x10,0x1E,0x10,0x10,0x10], // 'F'
+    [0x0E,0x11,0x10,0x17,0x11,0x11,0x0F], // 'G'
+    [0x11,0x11,0x11,0x1F,0x11,0x11,0x11], // 'H'
+    [0x0E,0x04,0x04,0x04,0x04,0x04,0x0E], // 'I'
+    [0x07,0x02,0x02,0x02,0x02,0x12,0x0C], // 'J'
+    [0x11,0x12,0x14,0x18,0x14,0x12,0x11], // 'K'
+    [0x10,0x10,0x10,0x10,0x10,0x10,0x1F], // 'L'
+    [0x11,0x1B,0x15,0x15,0x11,0x11,0x11], // 'M'
+    [0x11,0x11,0x19,0x15,0x13,0x11,0x11], // 'N'
+    [0x0E,0x11,0x11,0x11,0x11,0x11,0x0E], // 'O'
+    [0x1E,0x11,0x11,0x1E,0x10,0x10,0x10], // 'P'
+    [0x0E,0x11,0x11,0x11,0x15,0x12,0x0D], // 'Q'
+    [0x1E,0x11,0x11,0x1E,0x14,0x12,0x11], // 'R'
+    [0x0F,0x10,0x10,0x0E,0x01,0x01,0x1E], // 'S'
+    [0x1F,0x04,0x04,0x04,0x04,0x04,0x04], // 'T'
+    [0x11,0x11,0x11,0x11,0x11,0x11,0x0E], // 'U'
+    [0x11,0x11,0x11,0x11,0x11,0x0A,0x04], // 'V'
+    [0x11,0x11,0x11,0x15,0x15,0x15,0x0A], // 'W'
+    [0x11,0x11,0x0A,0x04,0x0A,0x11,0x11], // 'X'
+    [0x11,0x11,0x11,0x0A,0x04,0x04,0x04], // 'Y'
+    [0x1F,0x01,0x02,0x04,0x08,0x10,0x1F], // 'Z'
+    [0x0E,0x08,0x08,0x08,0x08,0x08,0x0E], // '['
+    [0x00,0x10,0x08,0x04,0x02,0x01,0x00], // '\\'
+    [0x0E,0x02,0x02,0x02,0x02,0x02,0x0E], // ']'
+    [0x04,0x0A,0x11,0x00,0x00,0x00,0x00], // '^'
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x1F], // '_'
+];
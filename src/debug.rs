@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use web_sys::WebGlTexture;
+
+use crate::{circle, Paddle, Pong, RenderContext, Track, Vec2};
+use crate::ai::predict_path;
+use crate::collision::Shape;
+use crate::config::Mode;
+use crate::error::PongError;
+use crate::gl::VertexArray;
+use crate::text::TextRenderer;
+
+const FRAME_HISTORY: usize = 120;
+const LINE_WIDTH: f32 = 0.006;
+const CIRCLE_SEGMENTS: usize = 16;
+// How far ahead the velocity vector points, in milliseconds
+const VELOCITY_TIME: f32 = 200.0;
+
+const TEXT_SIZE: f32 = 0.04;
+const LINE_HEIGHT: f32 = 0.06;
+// Frame time at the top of the graph, in milliseconds
+const GRAPH_MAX: f32 = 50.0;
+const GRAPH_LEFT: f32 = -0.95;
+const GRAPH_WIDTH: f32 = 0.6;
+const GRAPH_BOTTOM: f32 = -0.95;
+const GRAPH_HEIGHT: f32 = 0.2;
+
+// Shows collision shapes, the ball's velocity and predicted path, and frame
+// statistics on top of the game
+pub struct DebugOverlay {
+    pub enabled: bool,
+    frame_times: VecDeque<f32>,
+    graphics: Graphics
+}
+
+struct Graphics {
+    text: TextRenderer,
+    vertices: VertexArray,
+    hitbox: WebGlTexture,
+    velocity: WebGlTexture,
+    path: WebGlTexture,
+    graph: WebGlTexture
+}
+
+// Thin quads for outlines and lines, drawn in a single color
+#[derive(Default)]
+struct Lines {
+    vertices: Vec<f32>
+}
+
+impl DebugOverlay {
+    pub fn new(ctx: &RenderContext) -> Result<DebugOverlay, PongError> {
+        Ok(DebugOverlay {
+            enabled: false,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            graphics: Graphics::new(ctx)?
+        })
+    }
+
+    pub fn reload(&mut self, ctx: &RenderContext) -> Result<(), PongError> {
+        self.graphics = Graphics::new(ctx)?;
+        Ok(())
+    }
+
+    pub fn record_frame(&mut self, milliseconds: f32) {
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(milliseconds);
+    }
+
+    pub fn render(&self, pong: &Pong) {
+        let ctx = &pong.ctx;
+        let draw_calls = ctx.draw_calls.get();
+        let graphics = &self.graphics;
+
+        let mut hitboxes = Lines::default();
+        for ball in &pong.balls {
+            hitboxes.shape(&ball.position, &ball.shape);
+        }
+        for paddle in [&pong.left, &pong.right] {
+            hitboxes.paddle(paddle);
+        }
+        if pong.config.mode == Mode::FourPlayer {
+            for paddle in [&pong.square.top, &pong.square.bottom] {
+                hitboxes.shape(&paddle.position, &paddle.shape);
+            }
+        }
+        if pong.config.mode == Mode::Doubles {
+            for paddle in [&pong.doubles.left, &pong.doubles.right] {
+                hitboxes.paddle(paddle);
+            }
+        }
+        if pong.config.mode == Mode::Breakout {
+            hitboxes.paddle(&pong.square.bottom);
+            for (position, shape) in pong.breakout.bricks() {
+                hitboxes.shape(position, shape);
+            }
+        }
+        // Doubles is played in the arena like classic
+        if matches!(pong.config.mode, Mode::Classic | Mode::Doubles) {
+            for (position, shape) in pong.arena.obstacles() {
+                hitboxes.shape(&position, &shape);
+            }
+            for (a, b) in pong.arena.walls(&pong.config) {
+                hitboxes.line(&a, &b);
+            }
+        }
+        graphics.vertices.draw(ctx, &graphics.hitbox, &hitboxes.vertices);
+
+        let speed = pong.config.ball_speed;
+        let mut velocity = Lines::default();
+        let mut path = Lines::default();
+        for ball in &pong.balls {
+            velocity.line(&ball.position, &Vec2::new(ball.position.x + ball.velocity.x * speed * VELOCITY_TIME,
+                                                     ball.position.y + ball.velocity.y * speed * VELOCITY_TIME));
+            if pong.config.mode == Mode::Circle {
+                continue;
+            }
+            let points = predict_path(&ball.position, &ball.velocity, pong.config.wall, pong.config.goal_line);
+            for segment in points.windows(2) {
+                path.line(&segment[0], &segment[1]);
+            }
+        }
+        graphics.vertices.draw(ctx, &graphics.velocity, &velocity.vertices);
+        graphics.vertices.draw(ctx, &graphics.path, &path.vertices);
+
+        let mut graph = Lines::default();
+        graph.line(&Vec2::new(GRAPH_LEFT, GRAPH_BOTTOM), &Vec2::new(GRAPH_LEFT + GRAPH_WIDTH, GRAPH_BOTTOM));
+        let step = GRAPH_WIDTH / (FRAME_HISTORY - 1) as f32;
+        let graph_point = |i: usize, time: f32| Vec2::new(
+            GRAPH_LEFT + i as f32 * step,
+            GRAPH_BOTTOM + time.min(GRAPH_MAX) / GRAPH_MAX * GRAPH_HEIGHT);
+        for (i, (a, b)) in self.frame_times.iter().zip(self.frame_times.iter().skip(1)).enumerate() {
+            graph.line(&graph_point(i, *a), &graph_point(i + 1, *b));
+        }
+        graphics.vertices.draw(ctx, &graphics.graph, &graph.vertices);
+
+        let frame_time = if self.frame_times.is_empty() {
+            0.0
+        } else {
+            self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+        };
+        let fps = if frame_time > 0.0 { 1000.0 / frame_time } else { 0.0 };
+        let tail: usize = pong.balls.iter().map(|ball| ball.tail.particles.len()).sum();
+        let lines = [
+            format!("FPS {:.0}", fps),
+            format!("FRAME {:.1} MS", frame_time),
+            format!("BALLS {} TAIL {} SPARKS {}", pong.balls.len(), tail, pong.sparks.particles.len()),
+            format!("DRAWS {}", draw_calls)
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            graphics.text.render(ctx, line, -0.95, 0.95 - i as f32 * LINE_HEIGHT, TEXT_SIZE, 1.0);
+        }
+    }
+}
+
+impl Graphics {
+    fn new(ctx: &RenderContext) -> Result<Graphics, PongError> {
+        Ok(Graphics {
+            text: TextRenderer::new(ctx)?,
+            vertices: VertexArray::new(ctx)?,
+            hitbox: ctx.load_texture(&[0x00, 0xFF, 0x00, 0xFF], 1, 1)?,
+            velocity: ctx.load_texture(&[0xFF, 0xFF, 0x00, 0xFF], 1, 1)?,
+            path: ctx.load_texture(&[0x00, 0xCC, 0xFF, 0xAA], 1, 1)?,
+            graph: ctx.load_texture(&[0xFF, 0x88, 0x00, 0xFF], 1, 1)?
+        })
+    }
+}
+
+impl Lines {
+    fn line(&mut self, a: &Vec2, b: &Vec2) {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return;
+        }
+        let nx = -dy / length * LINE_WIDTH / 2.0;
+        let ny = dx / length * LINE_WIDTH / 2.0;
+        self.vertices.extend_from_slice(&[
+            a.x + nx, a.y + ny, 0.5, 0.5,  a.x - nx, a.y - ny, 0.5, 0.5,  b.x - nx, b.y - ny, 0.5, 0.5,
+            a.x + nx, a.y + ny, 0.5, 0.5,  b.x - nx, b.y - ny, 0.5, 0.5,  b.x + nx, b.y + ny, 0.5, 0.5
+        ]);
+    }
+
+    fn circle(&mut self, center: &Vec2, radius: f32) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            Vec2::new(center.x + radius * angle.cos(), center.y + radius * angle.sin())
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(&point(i), &point(i + 1));
+        }
+    }
+
+    fn paddle(&mut self, paddle: &Paddle) {
+        let radius = match paddle.track {
+            Track::Arc { radius, .. } => radius,
+            _ => return self.shape(&paddle.position, &paddle.shape)
+        };
+        let (center, half_angle) = circle::span(paddle, radius);
+        let half_width = paddle.shape.extent().x;
+        let point = |radius: f32, angle: f32| Vec2::new(radius * angle.cos(), radius * angle.sin());
+        for i in 0..CIRCLE_SEGMENTS {
+            let a = center - half_angle + 2.0 * half_angle * i as f32 / CIRCLE_SEGMENTS as f32;
+            let b = center - half_angle + 2.0 * half_angle * (i + 1) as f32 / CIRCLE_SEGMENTS as f32;
+            for r in [radius - half_width, radius + half_width] {
+                self.line(&point(r, a), &point(r, b));
+            }
+        }
+        for angle in [center - half_angle, center + half_angle] {
+            self.circle(&point(radius, angle), half_width);
+        }
+    }
+
+    fn shape(&mut self, position: &Vec2, shape: &Shape) {
+        match *shape {
+            Shape::Aabb { half_width, half_height } => {
+                let corners = [
+                    Vec2::new(position.x - half_width, position.y - half_height),
+                    Vec2::new(position.x + half_width, position.y - half_height),
+                    Vec2::new(position.x + half_width, position.y + half_height),
+                    Vec2::new(position.x - half_width, position.y + half_height)
+                ];
+                for i in 0..corners.len() {
+                    self.line(&corners[i], &corners[(i + 1) % corners.len()]);
+                }
+            },
+            Shape::Circle { radius } => self.circle(position, radius),
+            Shape::Capsule { half_length, radius } => {
+                let top = Vec2::new(position.x, position.y + half_length);
+                let bottom = Vec2::new(position.x, position.y - half_length);
+                self.circle(&top, radius);
+                self.circle(&bottom, radius);
+                for side in [-radius, radius] {
+                    self.line(&Vec2::new(top.x + side, top.y), &Vec2::new(bottom.x + side, bottom.y));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{paddle_shape, Ball, Models, Paddle, RenderContext, Side, Track, Vec2};
+use crate::config::{DoublesLayout, GameConfig, Mode};
+use crate::text::TextRenderer;
+
+const TEXT_SIZE: f32 = 0.04;
+
+// Doubles mode: a second paddle for each side, either in front of the first or
+// sharing the goal line with it, each half covering half the goal. Hits are
+// counted for every paddle.
+pub struct Doubles {
+    pub left: Paddle,
+    pub right: Paddle,
+    // Hits by the left and right paddles, then their partners
+    hits: [u32; 4]
+}
+
+impl Doubles {
+    pub fn new(config: &GameConfig) -> Doubles {
+        let shape = paddle_shape(config);
+        Doubles {
+            left: Paddle::new(Vec2::new(-config.doubles_depth, 0.0), Track::Vertical, shape),
+            right: Paddle::new(Vec2::new(config.doubles_depth, 0.0), Track::Vertical, shape),
+            hits: [0; 4]
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.hits = [0; 4];
+    }
+
+    // Places the partners and gives every paddle its zone. The first paddles
+    // of each side take the top half of the goal in the zones layout.
+    pub fn apply_config(&mut self, config: &GameConfig, left: &mut Paddle, right: &mut Paddle) {
+        let zones = config.mode == Mode::Doubles && config.doubles_layout == DoublesLayout::Zones;
+        let x = if zones { config.paddle_x } else { config.doubles_depth };
+        self.left.position.x = -x;
+        self.right.position.x = x;
+
+        // Zones keep the paddles' ends from crossing the middle of the goal
+        let half = paddle_shape(config).extent().y.min(config.paddle_limit);
+        let (top, bottom) = if zones {
+            (Some((half, config.paddle_limit)), Some((-config.paddle_limit, -half)))
+        } else {
+            (None, None)
+        };
+        for paddle in [&mut *left, &mut *right] {
+            paddle.zone = top;
+            paddle.slide(0.0, config.paddle_limit);
+        }
+        for paddle in [&mut self.left, &mut self.right] {
+            paddle.zone = bottom;
+            paddle.set_shape(paddle_shape(config));
+            paddle.slide(0.0, config.paddle_limit);
+        }
+    }
+
+    // Paddles only play balls heading for their own goal, letting returns from
+    // behind through the paddles in front
+    pub fn plays(side: Side, ball: &Ball) -> bool {
+        ball.velocity.dot(&side.outward()) > 0.0
+    }
+
+    // Counts hits by each paddle, in the order of `hits`
+    pub fn add_hits(&mut self, hits: [u32; 4]) {
+        for (total, count) in self.hits.iter_mut().zip(hits) {
+            *total += count;
+        }
+    }
+
+    pub fn hits(&self) -> [u32; 4] {
+        self.hits
+    }
+
+    // Draws everyone's hits above their side of the field
+    pub fn render(&self, models: &Models, ctx: &RenderContext) {
+        let [left, right, left_partner, right_partner] = self.hits;
+        let left = format!("HITS {} {}", left, left_partner);
+        let right = format!("HITS {} {}", right, right_partner);
+        models.text.render(ctx, &left, -0.95, 0.99, TEXT_SIZE, 0.6);
+        let x = 0.95 - TextRenderer::width(&right, TEXT_SIZE);
+        models.text.render(ctx, &right, x, 0.99, TEXT_SIZE, 0.6);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paddles(config: &GameConfig) -> (Doubles, Paddle, Paddle) {
+        let shape = paddle_shape(config);
+        let left = Paddle::new(Vec2::new(-config.paddle_x, 0.0), Track::Vertical, shape);
+        let right = Paddle::new(Vec2::new(config.paddle_x, 0.0), Track::Vertical, shape);
+        (Doubles::new(config), left, right)
+    }
+
+    #[test]
+    fn zones_split_each_goal_in_half() {
+        let config = GameConfig {
+            mode: Mode::Doubles, doubles_layout: DoublesLayout::Zones, ..GameConfig::default()
+        };
+        let (mut doubles, mut left, mut right) = paddles(&config);
+        doubles.apply_config(&config, &mut left, &mut right);
+
+        let half = paddle_shape(&config).extent().y;
+        let limit = config.paddle_limit;
+        for paddle in [&left, &right] {
+            assert_eq!(paddle.zone, Some((half, limit)));
+            assert_eq!(paddle.position.y, half);
+        }
+        for (partner, x) in [(&doubles.left, -config.paddle_x), (&doubles.right, config.paddle_x)] {
+            assert_eq!(partner.zone, Some((-limit, -half)));
+            assert_eq!(partner.position.x, x);
+            assert_eq!(partner.position.y, -half);
+        }
+
+        // Paddles can't leave their half of the goal
+        left.slide(-1.0, limit);
+        doubles.left.slide(1.0, limit);
+        assert_eq!(left.position.y, half);
+        assert_eq!(doubles.left.position.y, -half);
+    }
+
+    #[test]
+    fn depth_partners_stand_in_front_and_let_returns_through() {
+        let config = GameConfig { mode: Mode::Doubles, ..GameConfig::default() };
+        let (mut doubles, mut left, mut right) = paddles(&config);
+        doubles.apply_config(&config, &mut left, &mut right);
+
+        assert_eq!(doubles.left.position.x, -config.doubles_depth);
+        assert_eq!(doubles.right.position.x, config.doubles_depth);
+        assert!([&left, &right, &doubles.left, &doubles.right].iter().all(|paddle| paddle.zone.is_none()));
+
+        // A ball returned by the left paddle passes its partner on the way out
+        let outgoing = Ball::new(Vec2::new(-config.doubles_depth, 0.0), Vec2::new(1.0, 0.2), 0);
+        assert!(!Doubles::plays(Side::Left, &outgoing));
+        assert!(Doubles::plays(Side::Right, &outgoing));
+        let incoming = Ball::new(Vec2::new(-config.doubles_depth, 0.0), Vec2::new(-1.0, 0.2), 0);
+        assert!(Doubles::plays(Side::Left, &incoming));
+        assert!(!Doubles::plays(Side::Right, &incoming));
+    }
+
+    #[test]
+    fn hits_add_up_per_paddle() {
+        let (mut doubles, _, _) = paddles(&GameConfig::default());
+        doubles.add_hits([1, 0, 2, 0]);
+        doubles.add_hits([0, 3, 1, 1]);
+        assert_eq!(doubles.hits(), [1, 3, 3, 1]);
+        doubles.reset();
+        assert_eq!(doubles.hits(), [0; 4]);
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+#[derive(Debug)]
+pub enum PongError {
+    Dom(String),
+    WebGl(String),
+    Shader(String),
+    Audio(String),
+    Config(String),
+    Api(String)
+}
+
+impl PongError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PongError::Dom(_) => "dom",
+            PongError::WebGl(_) => "webgl",
+            PongError::Shader(_) => "shader",
+            PongError::Audio(_) => "audio",
+            PongError::Config(_) => "config",
+            PongError::Api(_) => "api"
+        }
+    }
+    pub fn message(&self) -> &str {
+        match self {
+            PongError::Dom(message)
+            | PongError::WebGl(message)
+            | PongError::Shader(message)
+            | PongError::Audio(message)
+            | PongError::Config(message)
+            | PongError::Api(message) => message
+        }
+    }
+}
+
+impl fmt::Display for PongError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} error: {}", self.kind(), self.message())
+    }
+}
+
+// Errors reach JavaScript as `Error` objects named `PongError` with a `kind`
+// property telling which subsystem failed
+impl From<PongError> for JsValue {
+    fn from(error: PongError) -> JsValue {
+        let js_error = js_sys::Error::new(error.message());
+        js_error.set_name("PongError");
+        let _ = Reflect::set(&js_error, &"kind".into(), &error.kind().into());
+        js_error.into()
+    }
+}
+
+// Describes an exception thrown by a browser API
+pub fn describe(value: JsValue) -> String {
+    if let Some(error) = value.dyn_ref::<js_sys::Error>() {
+        String::from(error.message())
+    } else if let Some(message) = value.as_string() {
+        message
+    } else {
+        format!("{:?}", value)
+    }
+}
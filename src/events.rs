@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use js_sys::{Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::Side;
+use crate::error::PongError;
+
+pub enum GameEvent {
+    PointScored { scorer: Side, left_score: u32, right_score: u32 },
+    PaddleHit { side: Side, rally: u32 },
+    WallBounce { top: bool },
+    Serve { dx: f32, dy: f32 },
+    MatchOver { winner: Side, left_score: u32, right_score: u32 },
+    RallyEnded { length: u32 },
+    BallSpawned { dx: f32, dy: f32, balls: u32 },
+    PowerUp { effect: String, side: Side },
+    LifeLost { side: Side, lives: u32 },
+    LastStanding { winner: Option<Side> },
+    BrickDestroyed { remaining: u32 },
+    LevelCleared { level: u32 },
+    GameOver { level: u32, score: u32 },
+    PracticeOver { hits: u32, misses: u32 }
+}
+
+const EVENT_NAMES: [&str; 14] = ["point", "paddle_hit", "wall_bounce", "serve", "match_over", "rally",
+                                "ball_spawn", "powerup", "life_lost", "last_standing", "brick",
+                                "level_cleared", "game_over", "practice_over"];
+
+#[derive(Clone, Default)]
+pub struct EventHandlers {
+    handlers: HashMap<&'static str, Vec<Function>>
+}
+
+impl GameEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            GameEvent::PointScored { .. } => "point",
+            GameEvent::PaddleHit { .. } => "paddle_hit",
+            GameEvent::WallBounce { .. } => "wall_bounce",
+            GameEvent::Serve { .. } => "serve",
+            GameEvent::MatchOver { .. } => "match_over",
+            GameEvent::RallyEnded { .. } => "rally",
+            GameEvent::BallSpawned { .. } => "ball_spawn",
+            GameEvent::PowerUp { .. } => "powerup",
+            GameEvent::LifeLost { .. } => "life_lost",
+            GameEvent::LastStanding { .. } => "last_standing",
+            GameEvent::BrickDestroyed { .. } => "brick",
+            GameEvent::LevelCleared { .. } => "level_cleared",
+            GameEvent::GameOver { .. } => "game_over",
+            GameEvent::PracticeOver { .. } => "practice_over"
+        }
+    }
+    fn to_js(&self) -> JsValue {
+        let event = Object::new();
+        let set = |key: &str, value: JsValue| {
+            let _ = Reflect::set(&event, &key.into(), &value);
+        };
+        set("type", self.name().into());
+
+        match *self {
+            GameEvent::PointScored { scorer, left_score, right_score } => {
+                set("scorer", scorer.name().into());
+                set("leftScore", left_score.into());
+                set("rightScore", right_score.into());
+            },
+            GameEvent::PaddleHit { side, rally } => {
+                set("side", side.name().into());
+                set("rally", rally.into());
+            },
+            GameEvent::WallBounce { top } => {
+                set("wall", (if top { "top" } else { "bottom" }).into());
+            },
+            GameEvent::Serve { dx, dy } => {
+                set("dx", dx.into());
+                set("dy", dy.into());
+            },
+            GameEvent::MatchOver { winner, left_score, right_score } => {
+                set("winner", winner.name().into());
+                set("leftScore", left_score.into());
+                set("rightScore", right_score.into());
+            },
+            GameEvent::RallyEnded { length } => {
+                set("length", length.into());
+            },
+            GameEvent::BallSpawned { dx, dy, balls } => {
+                set("dx", dx.into());
+                set("dy", dy.into());
+                set("balls", balls.into());
+            },
+            GameEvent::PowerUp { ref effect, side } => {
+                set("effect", effect.into());
+                set("side", side.name().into());
+            },
+            GameEvent::LifeLost { side, lives } => {
+                set("side", side.name().into());
+                set("lives", lives.into());
+            },
+            GameEvent::LastStanding { winner } => {
+                set("winner", winner.map_or(JsValue::NULL, |side| side.name().into()));
+            },
+            GameEvent::BrickDestroyed { remaining } => {
+                set("remaining", remaining.into());
+            },
+            GameEvent::LevelCleared { level } => {
+                set("level", level.into());
+            },
+            GameEvent::GameOver { level, score } => {
+                set("level", level.into());
+                set("score", score.into());
+            },
+            GameEvent::PracticeOver { hits, misses } => {
+                set("hits", hits.into());
+                set("misses", misses.into());
+            }
+        }
+
+        event.into()
+    }
+}
+
+impl EventHandlers {
+    pub fn add(&mut self, name: &str, callback: Function) -> Result<(), PongError> {
+        let name = EVENT_NAMES.iter().find(|n| **n == name)
+            .ok_or_else(|| PongError::Api(format!("Unknown event {}, expected one of {}",
+                                                  name, EVENT_NAMES.join(", "))))?;
+        self.handlers.entry(name).or_default().push(callback);
+        Ok(())
+    }
+    pub fn remove(&mut self, name: &str, callback: &Function) {
+        if let Some(callbacks) = self.handlers.get_mut(name) {
+            callbacks.retain(|c| c != callback);
+        }
+    }
+    pub fn dispatch(&self, events: &[GameEvent]) {
+        for event in events {
+            if let Some(callbacks) = self.handlers.get(event.name()) {
+                let value = event.to_js();
+                for callback in callbacks {
+                    if let Err(e) = callback.call1(&JsValue::NULL, &value) {
+                        gloo::console::error!("Error in game event handler:", e);
+                    }
+                }
+            }
+        }
+    }
+}
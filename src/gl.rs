@@ -0,0 +1,173 @@
+use js_sys::{Float32Array, Object};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    HtmlCanvasElement, WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlShader,
+    WebGlTexture, WebGlUniformLocation, WebGlVertexArrayObject
+};
+
+use crate::{RenderContext, GL};
+use crate::error::{describe, PongError};
+
+// A WebGL 2 context where available, otherwise WebGL 1. Calls shared by both
+// are forwarded, WebGL 2 features are used by matching on the variant.
+#[derive(Clone)]
+pub enum Gl {
+    WebGl1(web_sys::WebGlRenderingContext),
+    WebGl2(WebGl2RenderingContext)
+}
+
+macro_rules! forward {
+    ($(fn $name:ident(&self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;)*) => {
+        impl Gl {
+            $(
+                #[allow(clippy::too_many_arguments)]
+                pub fn $name(&self $(, $arg: $ty)*) $(-> $ret)? {
+                    match self {
+                        Gl::WebGl1(gl) => gl.$name($($arg),*),
+                        Gl::WebGl2(gl) => gl.$name($($arg),*)
+                    }
+                }
+            )*
+        }
+    }
+}
+
+forward! {
+    fn is_context_lost(&self) -> bool;
+    fn drawing_buffer_width(&self) -> i32;
+    fn drawing_buffer_height(&self) -> i32;
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32);
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32);
+    fn clear(&self, mask: u32);
+    fn enable(&self, cap: u32);
+    fn depth_func(&self, func: u32);
+    fn blend_func(&self, sfactor: u32, dfactor: u32);
+
+    fn create_shader(&self, type_: u32) -> Option<WebGlShader>;
+    fn shader_source(&self, shader: &WebGlShader, source: &str);
+    fn compile_shader(&self, shader: &WebGlShader);
+    fn get_shader_parameter(&self, shader: &WebGlShader, pname: u32) -> JsValue;
+    fn get_shader_info_log(&self, shader: &WebGlShader) -> Option<String>;
+    fn create_program(&self) -> Option<WebGlProgram>;
+    fn attach_shader(&self, program: &WebGlProgram, shader: &WebGlShader);
+    fn link_program(&self, program: &WebGlProgram);
+    fn get_program_parameter(&self, program: &WebGlProgram, pname: u32) -> JsValue;
+    fn get_program_info_log(&self, program: &WebGlProgram) -> Option<String>;
+    fn use_program(&self, program: Option<&WebGlProgram>);
+    fn get_attrib_location(&self, program: &WebGlProgram, name: &str) -> i32;
+    fn get_uniform_location(&self, program: &WebGlProgram, name: &str) -> Option<WebGlUniformLocation>;
+
+    fn uniform1i(&self, location: Option<&WebGlUniformLocation>, x: i32);
+    fn uniform1f(&self, location: Option<&WebGlUniformLocation>, x: f32);
+    fn uniform2f(&self, location: Option<&WebGlUniformLocation>, x: f32, y: f32);
+    fn uniform4f(&self, location: Option<&WebGlUniformLocation>, x: f32, y: f32, z: f32, w: f32);
+
+    fn create_buffer(&self) -> Option<WebGlBuffer>;
+    fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>);
+    fn buffer_data_with_array_buffer_view(&self, target: u32, data: &Object, usage: u32);
+    fn enable_vertex_attrib_array(&self, index: u32);
+    fn vertex_attrib_pointer_with_i32(&self, index: u32, size: i32, type_: u32, normalized: bool,
+                                      stride: i32, offset: i32);
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32);
+
+    fn create_texture(&self) -> Option<WebGlTexture>;
+    fn active_texture(&self, texture: u32);
+    fn bind_texture(&self, target: u32, texture: Option<&WebGlTexture>);
+    fn tex_parameteri(&self, target: u32, pname: u32, param: i32);
+    fn tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        &self, target: u32, level: i32, internal_format: i32, width: i32, height: i32,
+        border: i32, format: u32, type_: u32, pixels: Option<&[u8]>) -> Result<(), JsValue>;
+}
+
+impl Gl {
+    // Prefers WebGL 2 when `webgl2` is set, falling back to WebGL 1 when the
+    // browser does not support it
+    pub fn create(canvas: &HtmlCanvasElement, webgl2: bool) -> Result<Gl, PongError> {
+        let options = Object::new();
+        let _ = js_sys::Reflect::set(&options, &"alpha".into(), &false.into());
+
+        if webgl2 {
+            match canvas.get_context_with_context_options("webgl2", &options) {
+                Ok(Some(ctx)) => return ctx.dyn_into().map(Gl::WebGl2)
+                    .map_err(|_| PongError::WebGl(String::from("Unexpected WebGL 2 context type"))),
+                Ok(None) => (),
+                Err(e) => gloo::console::warn!(format!("Unable to create WebGL 2 context: {}", describe(e)))
+            }
+        }
+
+        canvas.get_context_with_context_options("webgl", &options)
+            .map_err(|e| PongError::WebGl(format!("Unable to create WebGL context: {}", describe(e))))?
+            .ok_or_else(|| PongError::WebGl(String::from("WebGL is not supported")))?
+            .dyn_into()
+            .map(Gl::WebGl1)
+            .map_err(|_| PongError::WebGl(String::from("Unexpected WebGL context type")))
+    }
+}
+
+// A vertex buffer in the x, y, u, v layout read by the shaders. On WebGL 2 the
+// attribute setup is recorded once in a vertex array object, on WebGL 1 it is
+// repeated on every bind.
+#[derive(Clone)]
+pub struct VertexArray {
+    buffer: WebGlBuffer,
+    vao: Option<WebGlVertexArrayObject>
+}
+
+impl VertexArray {
+    pub fn new(ctx: &RenderContext) -> Result<VertexArray, PongError> {
+        let buffer = ctx.gl.create_buffer()
+            .ok_or_else(|| PongError::WebGl(String::from("Unable to create vertex buffer")))?;
+        let vao = match &ctx.gl {
+            Gl::WebGl1(_) => None,
+            Gl::WebGl2(gl) => {
+                let vao = gl.create_vertex_array()
+                    .ok_or_else(|| PongError::WebGl(String::from("Unable to create vertex array")))?;
+                gl.bind_vertex_array(Some(&vao));
+                gl.bind_buffer(GL::ARRAY_BUFFER, Some(&buffer));
+                gl.enable_vertex_attrib_array(ctx.position);
+                gl.enable_vertex_attrib_array(ctx.texcoord);
+                set_attrib_pointers(ctx);
+                gl.bind_vertex_array(None);
+                Some(vao)
+            }
+        };
+        Ok(VertexArray { buffer, vao })
+    }
+
+    pub fn upload(&self, ctx: &RenderContext, vertices: &[f32], usage: u32) {
+        ctx.gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buffer));
+        ctx.gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &Float32Array::from(vertices), usage);
+    }
+
+    pub fn bind(&self, ctx: &RenderContext) {
+        match (&ctx.gl, &self.vao) {
+            (Gl::WebGl2(gl), Some(vao)) => gl.bind_vertex_array(Some(vao)),
+            _ => {
+                ctx.gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buffer));
+                set_attrib_pointers(ctx);
+            }
+        }
+    }
+
+    // Uploads and draws vertices in field coordinates with a single texture,
+    // for shapes rebuilt every frame
+    pub fn draw(&self, ctx: &RenderContext, texture: &WebGlTexture, vertices: &[f32]) {
+        if vertices.is_empty() {
+            return;
+        }
+        self.upload(ctx, vertices, GL::DYNAMIC_DRAW);
+        self.bind(ctx);
+        ctx.gl.active_texture(GL::TEXTURE0);
+        ctx.gl.bind_texture(GL::TEXTURE_2D, Some(texture));
+        ctx.gl.uniform1i(Some(&ctx.sampler), 0);
+        ctx.gl.uniform1f(Some(&ctx.opacity), 1.0);
+        ctx.gl.uniform4f(Some(&ctx.offset), 0.0, 0.0, 0.0, 0.0);
+        ctx.draw(vertices.len() as i32 / 4);
+    }
+}
+
+fn set_attrib_pointers(ctx: &RenderContext) {
+    ctx.gl.vertex_attrib_pointer_with_i32(ctx.position, 2, GL::FLOAT, false, 16, 0);
+    ctx.gl.vertex_attrib_pointer_with_i32(ctx.texcoord, 2, GL::FLOAT, false, 16, 8);
+}
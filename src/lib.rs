@@ -1,13 +1,44 @@
+mod ai;
+mod arena;
+mod breakout;
+mod circle;
+mod clock;
+mod collision;
+mod command;
+mod config;
+mod console;
+mod crash;
 mod data;
-
-use js_sys::WebAssembly;
+mod debug;
+mod doubles;
+mod error;
+mod events;
+mod gl;
+mod multiball;
+mod music;
+mod powerup;
+mod replay;
+mod shader;
+mod solo;
+mod square;
+mod text;
+
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{
-    WebGlProgram, WebGlUniformLocation, WebGlRenderingContext, WebGlShader,
+    WebGlProgram, WebGlUniformLocation, WebGlRenderingContext, WebGl2RenderingContext,
     WebGlTexture, WebGlBuffer, AudioContext, AudioBuffer, HtmlCanvasElement,
-    KeyboardEvent, Event
+    KeyboardEvent, Event, Element
 };
+use collision::Shape;
+use command::{arg, Commands};
+use config::{GameConfig, Inertia, Mode};
+use error::{describe, PongError};
+use events::{EventHandlers, GameEvent};
+use gl::{Gl, VertexArray};
+use shader::{compile_shader, link_program, ShaderStage};
 use gloo::{
     render::{request_animation_frame, AnimationFrame},
     events::EventListener
@@ -16,20 +47,49 @@ use gloo::{
 // Convenience alias for referring to OpenGL constants
 type GL = WebGlRenderingContext;
 
-const PADDLE_SPEED: f32 = 0.001;
-const BALL_SPEED: f32 = 0.0012;
+const MAX_FRAME_DELTA: i32 = 100;
+const REPLAY_LABEL: &str = "Replay";
+const REPLAY_LABEL_SIZE: f32 = 0.06;
+
+// How far before the paddles slow motion starts on a match winning goal
+const SLOW_MOTION_DISTANCE: f32 = 0.2;
 
 const AUDIO_BUFFER_SIZE: usize = 8192;
-type WebGlVertexArray = i32;
 
+// Collision shapes, independent of the size of the art
+const BALL_SHAPE: Shape = Shape::Circle { radius: 0.05 };
+const PADDLE_SHAPE: Shape = Shape::Aabb { half_width: 0.05, half_height: 0.2 };
+const ROUNDED_PADDLE_SHAPE: Shape = Shape::Capsule { half_length: 0.15, radius: 0.05 };
+
+// Uniform buffer binding point of the per-frame uniform block
+const FRAME_BINDING: u32 = 0;
+// Bumpers cannot speed balls up beyond this
+const MAX_BALL_SPEED: f32 = 3.0;
+// How close to the end of its track a paddle with inertia has to get to bounce,
+// as arc tracks don't always land exactly on it
+const LIMIT_TOLERANCE: f32 = 1e-5;
+
+#[derive(Clone)]
 struct RenderContext {
-    gl: WebGlRenderingContext,
+    gl: Gl,
     program: WebGlProgram,
-    position: WebGlVertexArray,
-    texcoord: WebGlVertexArray,
+    position: u32,
+    texcoord: u32,
     offset: WebGlUniformLocation,
+    size: WebGlUniformLocation,
+    // Cosine and sine of the model's rotation
+    rotation: WebGlUniformLocation,
     sampler: WebGlUniformLocation,
     opacity: WebGlUniformLocation,
+    frame: FrameUniforms,
+    draw_calls: Cell<u32>
+}
+
+// Values set once per frame, in a uniform block on WebGL 2
+#[derive(Clone)]
+enum FrameUniforms {
+    Uniforms { scale: WebGlUniformLocation },
+    Block(WebGlBuffer)
 }
 
 #[derive(Clone)]
@@ -39,29 +99,75 @@ struct Vec2 {
 }
 
 struct Model {
-    vertex_buffer: WebGlBuffer,
+    vertices: VertexArray,
     num_vertices: u32,
-    texture: WebGlTexture,
-    extent: Vec2
+    texture: WebGlTexture
+}
+
+struct Models {
+    ball: Model,
+    ball_tail: Model,
+    paddle: Model,
+    spark: Model,
+    field: Model,
+    overlay: Model,
+    pause: Model,
+    text: text::TextRenderer
 }
 
 struct Ball {
     position: Vec2,
-    velocity: Vec2
+    velocity: Vec2,
+    shape: Shape,
+    tail: ParticleSystem,
+    last_hitter: Option<Side>,
+    // Sideways acceleration from a curve shot
+    curve: f32,
+    // Radians per millisecond, counterclockwise, and how far the ball has
+    // turned for drawing
+    spin: f32,
+    angle: f32
 }
 
+// Paddles slide along a track, `up` moving them towards positive y on
+// vertical and arc tracks and positive x on horizontal ones
 struct Paddle {
     position: Vec2,
+    track: Track,
+    shape: Shape,
+    // Part of the track the paddle's center is kept in, if not all of it
+    zone: Option<(f32, f32)>,
+    // Distance moved per millisecond over the last update
+    velocity: Vec2,
+    // Physical movement settings, and the speed along the track they give
+    inertia: Option<Inertia>,
+    speed: f32,
+    ai: Option<ai::Difficulty>,
     up: bool,
     down: bool,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Track {
+    Vertical,
+    Horizontal,
+    // Circle of `radius` around the center, with the paddle's position
+    // measured from the angle `home`
+    Arc { radius: f32, home: f32 }
+}
+
+// Where a ball bounced and which way it was sent, for spark effects
+struct Bounce {
+    position: Vec2,
+    direction: Vec2
+}
+
 struct Particle {
     position: Vec2,
     velocity: Vec2,
     acceleration: Vec2,
-    life: i32,
-    total_life: i32
+    life: f32,
+    total_life: f32
 }
 
 struct ParticleSystem {
@@ -69,252 +175,1097 @@ struct ParticleSystem {
     particles: Vec<Particle>,   
 }
 
+struct Audio {
+    ctx: AudioContext,
+    buffer: AudioBuffer,
+    music: music::Sequencer
+}
+
+struct Scoreboard {
+    left: Option<Element>,
+    right: Option<Element>
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Running,
+    Paused,
+    Stopped
+}
+
 struct Pong {
+    canvas: HtmlCanvasElement,
+    crash_id: u32,
+    config: GameConfig,
+    status: Status,
     ctx: RenderContext,
-    audio_ctx: AudioContext,
-    audio_buffer: AudioBuffer,
+    audio: Option<Audio>,
+    scoreboard: Scoreboard,
 
-    request_animation_frame_handle: AnimationFrame,
-    _key_down_event_listener_handle: EventListener,
-    _key_up_event_listener_handle: EventListener,
+    request_animation_frame_handle: Option<AnimationFrame>,
+    event_listener_handles: Vec<EventListener>,
 
     timestamp: i32,
+    clock: clock::Clock,
 
-    ball_model: Model,
-    ball_tail_model: Model,
-    paddle_model: Model,
-    spark_model: Model,
-    field_model: Model,
+    models: Models,
+    debug: debug::DebugOverlay,
+    console: console::Console,
+    context_lost: bool,
+    resume_after_restore: bool,
 
     beep: Vec<f32>,
     boop: Vec<f32>,
     bloop: Vec<f32>,
 
-    ball: Ball,
+    balls: Vec<Ball>,
     left: Paddle,
     right: Paddle,
+    spawn_timer: f32,
 
-    ball_tail: ParticleSystem,
     sparks: ParticleSystem,
+    recorder: replay::Recorder,
+    replay: Option<replay::Replay>,
+    powerups: powerup::PowerUps,
+    arena: arena::Arena,
+    arena_graphics: arena::Graphics,
+    circle: circle::CircleArena,
+    breakout: breakout::Breakout,
+    breakout_graphics: breakout::Graphics,
+    solo: solo::Solo,
+    doubles: doubles::Doubles,
+    square: square::FourPlayer,
+    mode: Mode,
 
     left_score: u32,
-    right_score: u32
+    right_score: u32,
+    rally: u32,
+
+    events: Vec<GameEvent>,
+    event_handlers: EventHandlers
 }
 
-static mut PONG: Option<Pong> = None;
+#[wasm_bindgen]
+pub struct PongGame {
+    pong: Rc<RefCell<Pong>>
+}
 
-#[wasm_bindgen(start)]
-pub fn start() -> Result<(), JsValue> {
-    let window = web_sys::window().unwrap();
-    let document = window.document().unwrap();
-    let canvas: HtmlCanvasElement = document.get_element_by_id("canvas").unwrap().dyn_into()?;
-    let ctx_options = js_sys::Object::new();
-    js_sys::Reflect::set(&ctx_options, &"alpha".into(), &false.into()).unwrap();
-    let gl: WebGlRenderingContext = canvas
-        .get_context_with_context_options("webgl", &ctx_options)?.unwrap().dyn_into()?;
+#[wasm_bindgen]
+impl PongGame {
+    /// Creates a game rendering to `canvas`, given either as a canvas element
+    /// or as a selector string. Score elements with classes `score_left` and
+    /// `score_right` inside the canvas' parent element are kept up to date.
+    ///
+    /// `config` is an optional object or JSON string overriding fields of
+    /// the default game configuration.
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: JsValue, config: JsValue) -> Result<PongGame, JsValue> {
+        crash::install();
+        if crash::crashed() {
+            return Err(PongError::Api(String::from("The game module has crashed")).into());
+        }
 
-    gl.clear_color(0.1, 0.1, 0.1, 1.0);
-    gl.enable(GL::DEPTH_TEST);
-    gl.enable(GL::BLEND);
-    gl.depth_func(GL::LEQUAL);
-    gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
-    gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        let config = GameConfig::from_js(config)?;
+        let canvas = find_canvas(canvas)?;
+        let pong = Rc::new(RefCell::new(Pong::new(canvas, config)?));
+        Pong::attach(&pong)?;
+        Ok(PongGame { pong })
+    }
+
+    /// Freezes the game and shows the pause overlay until `resume` is called.
+    pub fn pause(&self) {
+        crash::guard("PongGame.pause", || self.pong.borrow_mut().pause());
+    }
+
+    /// Continues a paused game, or starts a new match after `stop`.
+    pub fn resume(&self) {
+        crash::guard("PongGame.resume", || Pong::resume(&self.pong));
+    }
 
-    let vert_shader = compile_shader(&gl, GL::VERTEX_SHADER, data::VERTEX_SHADER)?;
-    let frag_shader = compile_shader(&gl, GL::FRAGMENT_SHADER, data::FRAGMENT_SHADER)?;
-    let program = link_program(&gl, &vert_shader, &frag_shader)?;
-    gl.use_program(Some(&program));
+    /// Ends the current match and halts the game until `resume` is called.
+    pub fn stop(&self) {
+        crash::guard("PongGame.stop", || self.pong.borrow_mut().stop());
+    }
 
-    let ctx = RenderContext::new(gl, program);
+    pub fn is_paused(&self) -> bool {
+        crash::guard("PongGame.is_paused", || self.pong.borrow().status != Status::Running)
+            .unwrap_or(true)
+    }
 
-    let ball_texture = ctx.load_texture(&data::BALL_TEXTURE, 4, 4);
-    let ball_tail_texture = ctx.load_texture(&data::BALL_TAIL_TEXTURE, 4, 4);
-    let spark_texture = ctx.load_texture(&data::SPARK_TEXTURE, 4, 4);
-    let paddle_texture = ctx.load_texture(&data::PADDLE_TEXTURE, 8, 8);
-    let field_texture = ctx.load_texture(&data::FIELD_TEXTURE, 8, 8);
+    /// Registers `callback` for game events of type `event`: `point`,
+    /// `paddle_hit`, `wall_bounce`, `serve`, `match_over`, `rally`,
+    /// `ball_spawn`, `powerup`, `life_lost`, `last_standing`, `brick`,
+    /// `level_cleared`, `game_over` or `practice_over`. The callback receives an object with a
+    /// `type` field and event details.
+    pub fn on(&self, event: &str, callback: js_sys::Function) -> Result<(), JsValue> {
+        crash::guard("PongGame.on", || self.pong.borrow_mut().event_handlers.add(event, callback))
+            .unwrap_or(Ok(()))
+            .map_err(JsValue::from)
+    }
 
-    let ball_model = Model::new(&ctx, &data::BALL_VERTICES, ball_texture);
-    let ball_tail_model = Model::new(&ctx, &data::BALL_TAIL_VERTICES, ball_tail_texture);
-    let spark_model = Model::new(&ctx, &data::SPARK_VERTICES, spark_texture);
-    let paddle_model = Model::new(&ctx, &data::PADDLE_VERTICES, paddle_texture);
-    let field_model = Model::new(&ctx, &data::FIELD_VERTICES, field_texture);
+    pub fn off(&self, event: &str, callback: &js_sys::Function) {
+        crash::guard("PongGame.off", || self.pong.borrow_mut().event_handlers.remove(event, callback));
+    }
 
-    let mut beep: Vec<f32> = Vec::with_capacity(AUDIO_BUFFER_SIZE);
-    let mut boop: Vec<f32> = Vec::with_capacity(AUDIO_BUFFER_SIZE);
-    let mut bloop: Vec<f32> = Vec::with_capacity(AUDIO_BUFFER_SIZE);
+    /// Shows or hides the debug overlay with collision shapes, the ball's
+    /// predicted path and frame statistics. F2 toggles it from the keyboard.
+    pub fn set_debug_overlay(&self, enabled: bool) {
+        crash::guard("PongGame.set_debug_overlay", || self.pong.borrow_mut().set_debug_overlay(enabled));
+    }
 
-    for i in 0..AUDIO_BUFFER_SIZE {
-        let sq64 = if i/64 % 2 == 0 { 0.1 } else { -0.1 };
-        let sq128 = if i/128 % 2 == 0 { 0.1 } else { -0.1 };
-        beep.push(sq64);
-        boop.push(sq128);
-        bloop.push(sq64 + sq128);
+    /// Adds a power-up to the game's registry, or replaces the one with the
+    /// same name. `effect` is an object or JSON string in the format of the
+    /// built-in power-ups.
+    pub fn add_powerup(&self, effect: JsValue) -> Result<(), JsValue> {
+        crash::guard("PongGame.add_powerup", || {
+            let effect = powerup::Effect::from_js(effect)?;
+            let p = &mut *self.pong.borrow_mut();
+            p.powerups.register(&p.ctx, effect)
+        }).unwrap_or(Ok(())).map_err(JsValue::from)
     }
 
-    let audio_ctx = AudioContext::new().unwrap();
-    let audio_buffer = audio_ctx.create_buffer(
-        1, (audio_ctx.sample_rate() * 2.0) as u32, audio_ctx.sample_rate()).unwrap();
+    /// Hits by each paddle in doubles mode since the match started: left,
+    /// right, left partner and right partner.
+    pub fn paddle_hits(&self) -> Vec<u32> {
+        crash::guard("PongGame.paddle_hits", || self.pong.borrow().doubles.hits().to_vec())
+            .unwrap_or_default()
+    }
 
-    let request_animation_frame_handle = request_animation_frame(on_animation_frame);
+    /// Adds a breakout level after the built-in ones, or replaces the one
+    /// with the same name. `level` is an object or JSON string in the format
+    /// of the built-in levels.
+    pub fn add_level(&self, level: JsValue) -> Result<(), JsValue> {
+        crash::guard("PongGame.add_level", || {
+            let p = &mut *self.pong.borrow_mut();
+            let level = breakout::Level::from_js(level, &p.config)?;
+            p.breakout.register(level);
+            Ok::<(), PongError>(())
+        }).unwrap_or(Ok(())).map_err(JsValue::from)
+    }
 
-    let _key_down_event_listener_handle = EventListener::new(&document, "keydown", |e: &Event| {
-        if let Some(e) = e.dyn_ref::<KeyboardEvent>() {
-            on_key(e.key_code(), true);
-        }
-    });
+    /// Adds an arena layout, replacing the one with the same name, and
+    /// switches to it. `layout` is an object or JSON string in the format of
+    /// the built-in arenas.
+    pub fn load_arena(&self, layout: JsValue) -> Result<(), JsValue> {
+        crash::guard("PongGame.load_arena", || {
+            let layout = arena::Layout::from_js(layout)?;
+            let p = &mut *self.pong.borrow_mut();
+            p.arena.load(layout)?;
+            p.config.arena = String::from(p.arena.name());
+            render(p);
+            Ok::<(), PongError>(())
+        }).unwrap_or(Ok(())).map_err(JsValue::from)
+    }
 
-    let _key_up_event_listener_handle = EventListener::new(&document, "keyup", |e: &Event| {
-        if let Some(e) = e.dyn_ref::<KeyboardEvent>() {
-            on_key(e.key_code(), false);
+    /// Stops the game and releases its event listeners and audio context.
+    pub fn destroy(self) {
+        crash::guard("PongGame.destroy", || self.pong.borrow_mut().detach());
+    }
+}
+
+fn find_canvas(canvas: JsValue) -> Result<HtmlCanvasElement, PongError> {
+    match canvas.as_string() {
+        Some(selector) => {
+            document()?.query_selector(&selector)
+                .map_err(|e| PongError::Dom(format!("Invalid selector {}: {}", selector, describe(e))))?
+                .ok_or_else(|| PongError::Dom(format!("No element matches {}", selector)))?
+                .dyn_into()
+                .map_err(|_| PongError::Dom(format!("{} is not a canvas", selector)))
+        },
+        None => canvas.dyn_into()
+            .map_err(|_| PongError::Dom(String::from("Expected a canvas element or a selector")))
+    }
+}
+
+fn register_crash_screen(canvas: &HtmlCanvasElement, ctx: &RenderContext) -> Result<u32, PongError> {
+    Ok(crash::register(crash::CrashScreen {
+        canvas: canvas.clone(),
+        ctx: ctx.clone(),
+        text: text::TextRenderer::new(ctx)?
+    }))
+}
+
+fn document() -> Result<web_sys::Document, PongError> {
+    web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| PongError::Dom(String::from("No document available")))
+}
+
+impl Pong {
+    fn new(canvas: HtmlCanvasElement, config: GameConfig) -> Result<Pong, PongError> {
+        let gl = Gl::create(&canvas, config.webgl2)?;
+        let ctx = RenderContext::init(gl)?;
+        let models = Models::new(&ctx)?;
+        let debug = debug::DebugOverlay::new(&ctx)?;
+        let mut commands = Commands::default();
+        register_commands(&mut commands);
+        let console = console::Console::new(&ctx, commands)?;
+        let powerups = powerup::PowerUps::new(&ctx)?;
+        let arena = arena::Arena::new(&config.arena)?;
+        let arena_graphics = arena::Graphics::new(&ctx)?;
+        let circle = circle::CircleArena::new(&ctx)?;
+        let breakout = breakout::Breakout::new(&config)?;
+        if config.mode == Mode::Breakout {
+            breakout.check(&config)?;
+        }
+        let breakout_graphics = breakout::Graphics::new(&ctx)?;
+        let crash_id = register_crash_screen(&canvas, &ctx)?;
+
+        let mut beep: Vec<f32> = Vec::with_capacity(AUDIO_BUFFER_SIZE);
+        let mut boop: Vec<f32> = Vec::with_capacity(AUDIO_BUFFER_SIZE);
+        let mut bloop: Vec<f32> = Vec::with_capacity(AUDIO_BUFFER_SIZE);
+
+        for i in 0..AUDIO_BUFFER_SIZE {
+            let sq64 = if i/64 % 2 == 0 { 0.1 } else { -0.1 };
+            let sq128 = if i/128 % 2 == 0 { 0.1 } else { -0.1 };
+            beep.push(sq64);
+            boop.push(sq128);
+            bloop.push(sq64 + sq128);
         }
-    });
 
-    unsafe {
-        PONG = Some(Pong {
-            ctx, audio_ctx, audio_buffer,
+        // The game is playable without sound, so audio failures are not fatal
+        let audio = match Audio::new() {
+            Ok(audio) => Some(audio),
+            Err(e) => {
+                gloo::console::warn!(e.to_string());
+                None
+            }
+        };
+
+        let scoreboard = Scoreboard::new(&canvas);
+        let paddle_shape = paddle_shape(&config);
+
+        let mut pong = Pong {
+            canvas,
+            crash_id,
+            status: Status::Running,
+            ctx, audio, scoreboard,
             timestamp: 0,
-            request_animation_frame_handle,
-            _key_down_event_listener_handle,
-            _key_up_event_listener_handle,
+            clock: clock::Clock::default(),
+            request_animation_frame_handle: None,
+            event_listener_handles: Vec::new(),
 
-            ball_model, ball_tail_model, paddle_model, spark_model, field_model,
+            models, debug, console,
+            context_lost: false,
+            resume_after_restore: false,
             beep, boop, bloop,
 
-            ball: Ball {
-                position: Vec2::zero(),
-                velocity: Vec2::new(1.0, 1.0),
-            },
-            left: Paddle {
-                position: Vec2::new(-0.9, 0.0),
-                up: false, down: false
-            },
-            right: Paddle {
-                position: Vec2::new(0.9, 0.0),
-                up: false, down: false
-            },
-            ball_tail: ParticleSystem::new(100),
-            sparks: ParticleSystem::new(100),
+            balls: vec![Ball::new(Vec2::zero(), Vec2::new(1.0, 1.0), config.ball_tail_particles)],
+            left: Paddle::new(Vec2::new(-config.paddle_x, 0.0), paddle_track(&config, Side::Left), paddle_shape),
+            right: Paddle::new(Vec2::new(config.paddle_x, 0.0), paddle_track(&config, Side::Right), paddle_shape),
+            spawn_timer: 0.0,
+            sparks: ParticleSystem::new(config.spark_particles),
+            recorder: replay::Recorder::default(),
+            replay: None,
+            powerups, arena, arena_graphics, circle, breakout, breakout_graphics,
+            solo: solo::Solo::default(),
+            doubles: doubles::Doubles::new(&config),
+            square: square::FourPlayer::new(&config),
+            mode: config.mode,
 
             left_score: 0,
-            right_score: 0
+            right_score: 0,
+            rally: 0,
+
+            events: Vec::new(),
+            event_handlers: EventHandlers::default(),
+            config
+        };
+        pong.apply_config();
+        Ok(pong)
+    }
+
+    fn attach(pong: &Rc<RefCell<Pong>>) -> Result<(), PongError> {
+        const KEY_P: u32 = 80;
+        const KEY_F2: u32 = 113;
+
+        let window = web_sys::window()
+            .ok_or_else(|| PongError::Dom(String::from("No window available")))?;
+        let document = document()?;
+        let canvas = pong.borrow().canvas.clone();
+
+        // Keys go to the focused canvas only, so several games on one page don't
+        // all react to the same key press. A tabindex makes the canvas focusable
+        if !canvas.has_attribute("tabindex") {
+            canvas.set_attribute("tabindex", "0")
+                .map_err(|e| PongError::Dom(format!("Unable to make canvas focusable: {}", describe(e))))?;
+        }
+
+        let weak = Rc::downgrade(pong);
+        let key_down = EventListener::new(&canvas, "keydown", move |e: &Event| {
+            crash::guard("keydown", || {
+                if let (Some(e), Some(pong)) = (e.dyn_ref::<KeyboardEvent>(), weak.upgrade()) {
+                    let console_open = pong.borrow().console.open;
+                    if (e.key() == "`" || e.code() == "Backquote") && !e.repeat() {
+                        pong.borrow_mut().toggle_console();
+                    } else if console_open {
+                        if !(e.ctrl_key() || e.meta_key() || e.alt_key()) {
+                            let p = &mut *pong.borrow_mut();
+                            console::on_key(p, &e.key());
+                            render(p);
+                        }
+                    } else if pong.borrow().replay.is_some() {
+                        let p = &mut *pong.borrow_mut();
+                        p.replay = None;
+                        render(p);
+                    } else if e.key_code() == KEY_P && !e.repeat() {
+                        Pong::toggle_pause(&pong);
+                    } else if e.key_code() == KEY_F2 && !e.repeat() {
+                        let p = &mut *pong.borrow_mut();
+                        p.set_debug_overlay(!p.debug.enabled);
+                    } else {
+                        on_key(&mut pong.borrow_mut(), e.key_code(), true);
+                    }
+                }
+            });
+        });
+
+        let weak = Rc::downgrade(pong);
+        let key_up = EventListener::new(&canvas, "keyup", move |e: &Event| {
+            crash::guard("keyup", || {
+                if let (Some(e), Some(pong)) = (e.dyn_ref::<KeyboardEvent>(), weak.upgrade()) {
+                    on_key(&mut pong.borrow_mut(), e.key_code(), false);
+                }
+            });
+        });
+
+        let weak = Rc::downgrade(pong);
+        let target = document.clone();
+        let visibility_change = EventListener::new(&document, "visibilitychange", move |_| {
+            crash::guard("visibilitychange", || {
+                if let (true, Some(pong)) = (target.hidden(), weak.upgrade()) {
+                    pong.borrow_mut().pause();
+                }
+            });
+        });
+
+        let weak = Rc::downgrade(pong);
+        let blur = EventListener::new(&window, "blur", move |_| {
+            crash::guard("blur", || {
+                if let Some(pong) = weak.upgrade() {
+                    pong.borrow_mut().pause();
+                }
+            });
+        });
+
+        // The key up of a key held while focus moves elsewhere never arrives
+        let weak = Rc::downgrade(pong);
+        let focus_lost = EventListener::new(&canvas, "blur", move |_| {
+            crash::guard("canvas blur", || {
+                if let Some(pong) = weak.upgrade() {
+                    pong.borrow_mut().release_keys();
+                }
+            });
+        });
+
+        // Preventing the default action of webglcontextlost allows the context
+        // to be restored later
+        let weak = Rc::downgrade(pong);
+        let context_lost = EventListener::new(&canvas, "webglcontextlost", move |e| {
+            e.prevent_default();
+            crash::guard("webglcontextlost", || {
+                if let Some(pong) = weak.upgrade() {
+                    pong.borrow_mut().lose_context();
+                }
+            });
         });
+
+        let weak = Rc::downgrade(pong);
+        let context_restored = EventListener::new(&canvas, "webglcontextrestored", move |_| {
+            crash::guard("webglcontextrestored", || {
+                if let Some(pong) = weak.upgrade() {
+                    Pong::restore_context(&pong);
+                }
+            });
+        });
+
+        let mut p = pong.borrow_mut();
+        p.event_listener_handles = vec![
+            key_down, key_up, visibility_change, blur, focus_lost, context_lost, context_restored
+        ];
+        p.request_animation_frame_handle = Some(request_next_frame(Rc::downgrade(pong)));
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        self.status = Status::Stopped;
+        self.request_animation_frame_handle = None;
+        self.event_listener_handles.clear();
+        if let Some(audio) = &mut self.audio {
+            audio.music.stop();
+        }
+    }
+
+    fn pause(&mut self) {
+        if self.status == Status::Running {
+            self.status = Status::Paused;
+            self.halt();
+        }
+    }
+
+    fn stop(&mut self) {
+        if self.status != Status::Stopped {
+            self.status = Status::Stopped;
+            self.reset_match();
+            self.halt();
+        }
+    }
+
+    fn halt(&mut self) {
+        self.request_animation_frame_handle = None;
+        if let Some(audio) = &mut self.audio {
+            audio.music.stop();
+        }
+        self.release_keys();
+        render(self);
+    }
+
+    fn resume(pong: &Rc<RefCell<Pong>>) {
+        let p = &mut *pong.borrow_mut();
+        if p.status == Status::Running || p.event_listener_handles.is_empty() {
+            return;
+        }
+        if p.context_lost {
+            p.resume_after_restore = true;
+            return;
+        }
+
+        p.status = Status::Running;
+        p.timestamp = 0;
+        if let Some(audio) = &mut p.audio {
+            audio.music.start(&audio.ctx);
+        }
+        p.request_animation_frame_handle = Some(request_next_frame(Rc::downgrade(pong)));
+    }
+
+    fn lose_context(&mut self) {
+        self.context_lost = true;
+        self.resume_after_restore = self.status == Status::Running;
+        self.pause();
+    }
+
+    // All WebGL objects are invalid after a context loss, so the program,
+    // textures and vertex buffers are recreated from scratch. Each part's
+    // `reload` or `Graphics::new` rebuilds its own resources here.
+    fn restore_context(pong: &Rc<RefCell<Pong>>) {
+        let resume = {
+            let p = &mut *pong.borrow_mut();
+            let restored = RenderContext::init(p.ctx.gl.clone()).and_then(|ctx| {
+                let models = Models::new(&ctx)?;
+                p.debug.reload(&ctx)?;
+                p.console.reload(&ctx)?;
+                p.powerups.reload(&ctx)?;
+                p.arena_graphics = arena::Graphics::new(&ctx)?;
+                p.circle.reload(&ctx)?;
+                p.breakout_graphics = breakout::Graphics::new(&ctx)?;
+                let crash_id = register_crash_screen(&p.canvas, &ctx)?;
+                crash::unregister(p.crash_id);
+                p.crash_id = crash_id;
+                p.ctx = ctx;
+                p.models = models;
+                Ok(())
+            });
+            if let Err(e) = restored {
+                gloo::console::error!(format!("Unable to restore WebGL context: {}", e));
+                return;
+            }
+
+            p.context_lost = false;
+            render(p);
+            std::mem::take(&mut p.resume_after_restore)
+        };
+        if resume {
+            Pong::resume(pong);
+        }
+    }
+
+    fn toggle_console(&mut self) {
+        self.console.open = !self.console.open;
+        self.release_keys();
+        render(self);
+    }
+
+    fn release_keys(&mut self) {
+        for paddle in [&mut self.left, &mut self.right, &mut self.square.top, &mut self.square.bottom,
+                       &mut self.doubles.left, &mut self.doubles.right] {
+            paddle.up = false;
+            paddle.down = false;
+        }
+    }
+
+    // Switches to a new configuration. Everything that can fail is checked
+    // before any game state changes, so a rejected configuration leaves the
+    // game as it was.
+    fn configure(&mut self, config: GameConfig) -> Result<(), PongError> {
+        config.validate()?;
+        if config.mode == Mode::Breakout {
+            self.breakout.check(&config)?;
+        }
+        // Last, as it switches the arena when it succeeds
+        self.arena.select(&config.arena)?;
+        self.config = config;
+        self.apply_config();
+        Ok(())
+    }
+
+    // The paddles on the left and right of the field, with the doubles
+    // partners after the first two
+    fn side_paddles(&self) -> Vec<(Side, Vec2)> {
+        let mut paddles = vec![(Side::Left, self.left.position.clone()), (Side::Right, self.right.position.clone())];
+        if self.config.mode == Mode::Doubles {
+            paddles.push((Side::Left, self.doubles.left.position.clone()));
+            paddles.push((Side::Right, self.doubles.right.position.clone()));
+        }
+        paddles
+    }
+
+    // Brings the game up to date after configuration changes
+    fn apply_config(&mut self) {
+        let config = &self.config;
+        let shape = paddle_shape(config);
+        self.left.track = paddle_track(config, Side::Left);
+        self.right.track = paddle_track(config, Side::Right);
+        self.left.position.x = -config.paddle_x;
+        self.right.position.x = config.paddle_x;
+        for paddle in [&mut self.left, &mut self.right] {
+            paddle.set_shape(shape);
+            paddle.slide(0.0, config.paddle_limit);
+        }
+        for ball in &mut self.balls {
+            ball.tail.max_particles = config.ball_tail_particles;
+        }
+        self.balls.truncate(config.max_balls);
+        self.sparks.max_particles = config.spark_particles;
+        self.square.apply_config(config);
+        self.doubles.apply_config(config, &mut self.left, &mut self.right);
+        self.breakout.apply_config(config);
+        let inertia = &config.paddle_inertia;
+        for (paddle, inertia) in [(&mut self.left, inertia.left), (&mut self.right, inertia.right),
+                                  (&mut self.square.top, inertia.top),
+                                  (&mut self.square.bottom, inertia.bottom),
+                                  (&mut self.doubles.left, inertia.left2),
+                                  (&mut self.doubles.right, inertia.right2)] {
+            paddle.inertia = inertia;
+            paddle.speed = 0.0;
+        }
+        if self.mode != self.config.mode {
+            self.mode = self.config.mode;
+            self.reset_match();
+        }
+    }
+
+    fn set_debug_overlay(&mut self, enabled: bool) {
+        self.debug.enabled = enabled;
+        render(self);
     }
 
-    Ok(())
+    fn toggle_pause(pong: &Rc<RefCell<Pong>>) {
+        let running = pong.borrow().status == Status::Running;
+        if running {
+            pong.borrow_mut().pause();
+        } else {
+            Pong::resume(pong);
+        }
+    }
+
+    fn reset_match(&mut self) {
+        self.left_score = 0;
+        self.right_score = 0;
+        self.rally = 0;
+        self.spawn_timer = 0.0;
+        self.balls = vec![Ball::new(Vec2::zero(), Vec2::new(1.0, 1.0), self.config.ball_tail_particles)];
+        self.sparks.particles.clear();
+        self.recorder.clear();
+        self.replay = None;
+        self.powerups.clear();
+        self.square.reset(&self.config);
+        self.breakout.reset(&self.config);
+        self.solo.reset();
+        self.doubles.reset();
+        // Practice balls only come from the launcher
+        if self.config.mode == Mode::Practice {
+            self.balls.clear();
+        }
+        self.scoreboard.set(0, 0);
+    }
 }
 
-pub fn on_animation_frame(timestamp: f64) {
+impl Drop for Pong {
+    fn drop(&mut self) {
+        crash::unregister(self.crash_id);
+        if let Some(audio) = &self.audio {
+            let _ = audio.ctx.close();
+        }
+    }
+}
+
+fn request_next_frame(pong: Weak<RefCell<Pong>>) -> AnimationFrame {
+    request_animation_frame(move |timestamp| {
+        if let Some(pong) = pong.upgrade() {
+            // Handlers run after the frame so that they can call back into the game
+            let dispatch = crash::guard("animation frame", || {
+                let mut p = pong.borrow_mut();
+                on_animation_frame(&mut p, timestamp);
+                p.request_animation_frame_handle = Some(request_next_frame(Rc::downgrade(&pong)));
+                (std::mem::take(&mut p.events), p.event_handlers.clone())
+            });
+            if let Some((events, handlers)) = dispatch {
+                crash::guard("event handlers", || handlers.dispatch(&events));
+            }
+        }
+    })
+}
+
+fn on_animation_frame(pong: &mut Pong, timestamp: f64) {
     let timestamp = timestamp as i32;
-    let pong = unsafe { PONG.as_mut().unwrap() };
+    if pong.timestamp != 0 {
+        pong.debug.record_frame((timestamp - pong.timestamp) as f32);
+    }
     let delta = match pong.timestamp {
         0 => 1,
-        x => timestamp - x
+        x => (timestamp - x).min(MAX_FRAME_DELTA)
     } as f32;
     pong.timestamp = timestamp;
 
-    let left = &mut pong.left;
-    let right = &mut pong.right;
-
-    let left_direction = if left.up == left.down { 0. } else if left.up { 1. } else { -1. };
-    let right_direction = if right.up == right.down { 0. } else if right.up { 1. } else { -1. };
-    left.position.y = clamp(left.position.y + left_direction * PADDLE_SPEED * delta, -0.8, 0.8);
-    right.position.y = clamp(right.position.y + right_direction * PADDLE_SPEED * delta, -0.8, 0.8);
-
-    let ball = &mut pong.ball;
-    ball.position.x += ball.velocity.x * delta * BALL_SPEED;
-    if collide(&ball.position, &pong.ball_model.extent,
-               &left.position, &pong.paddle_model.extent) 
-        || collide(&ball.position, &pong.ball_model.extent,
-                   &right.position, &pong.paddle_model.extent) {
-            ball.velocity.x = -ball.velocity.x;
-            ball.position.x += ball.velocity.x * delta * BALL_SPEED;
-            play_audio(&pong.beep);
-            create_sparks(&mut pong.sparks,
-                          (if ball.velocity.x > 0. { -1. } else { 1. })
-                          * pong.ball_model.extent.x + ball.position.x,
-                          ball.position.y, 2. * ball.velocity.x, 0.);
-        } else if ball.position.x.abs() > 1.05 {
-            if ball.position.x > 0.0 {
-                pong.left_score += 1;
-            } else {
-                pong.right_score += 1;
+    // The game waits while the last point is replayed
+    if let Some(replay) = &mut pong.replay {
+        if !replay.advance(delta) {
+            pong.replay = None;
+        }
+    } else {
+        let slow_motion = if match_winning_goal_imminent(pong) { pong.config.slow_motion } else { 1.0 };
+        let delta = pong.clock.advance(delta, slow_motion);
+        if delta > 0.0 {
+            match pong.config.mode {
+                Mode::Classic | Mode::Circle | Mode::Doubles => update(pong, delta, timestamp),
+                Mode::FourPlayer => square::update(pong, delta, timestamp),
+                Mode::Breakout => breakout::update(pong, delta, timestamp),
+                Mode::Squash | Mode::Practice => solo::update(pong, delta, timestamp)
             }
+        }
+    }
+
+    let match_point = pong.left_score.max(pong.right_score) + 1 >= pong.config.winning_score;
+    if let Some(audio) = &mut pong.audio {
+        audio.update_music(&music::Intensity { rally: pong.rally, match_point });
+    }
+
+    render(pong);
+}
+
+// Advances the simulation by `delta` milliseconds of game time
+fn update(pong: &mut Pong, delta: f32, timestamp: i32) {
+    let config = &pong.config;
+    // In the order doubles counts hits in
+    let mut paddles = vec![(Side::Left, &mut pong.left), (Side::Right, &mut pong.right)];
+    let doubles = config.mode == Mode::Doubles;
+    if doubles {
+        paddles.push((Side::Left, &mut pong.doubles.left));
+        paddles.push((Side::Right, &mut pong.doubles.right));
+    }
+
+    for (_, paddle) in paddles.iter_mut() {
+        if let Some(difficulty) = paddle.ai {
+            ai::control(paddle, difficulty, &pong.balls, config);
+        }
+    }
+
+    let powerups = &pong.powerups;
+    let modifiers = |side| powerups.modifiers(side);
+    for (side, paddle) in paddles.iter_mut() {
+        let side = *side;
+        let modifiers = modifiers(side);
+        let direction = if modifiers.reverse_controls { -paddle.direction() } else { paddle.direction() };
+        paddle.steer(direction, config.paddle_speed, delta, config.paddle_limit);
+        paddle.set_shape(paddle_shape(config).stretched(modifiers.paddle_scale));
+    }
+    let ball_speed = config.ball_speed * powerups.ball_speed();
+    let circle = config.mode == Mode::Circle;
+    if !circle {
+        pong.arena.update(delta);
+    }
+
+    let mut hits = [0; 4];
+    let mut paddle_hits = Vec::new();
+    let mut wall_bounces = Vec::new();
+    let mut spawns = 0;
+    if config.multiball {
+        pong.spawn_timer += delta;
+        if pong.spawn_timer >= config.ball_spawn_interval {
+            pong.spawn_timer = 0.0;
+            spawns += 1;
+        }
+    }
+
+    for ball in pong.balls.iter_mut() {
+        ball.advance(delta, ball_speed, config);
+
+        for (i, (side, paddle)) in paddles.iter().enumerate() {
+            let side = *side;
+            if doubles && !doubles::Doubles::plays(side, ball) {
+                continue;
+            }
+            let contact = match paddle.contact(ball) {
+                Some(contact) => contact,
+                None => continue
+            };
+            // A ball already moving away was only caught up by the paddle
+            let impact = match bounce(ball, contact, 1.0) {
+                Some(impact) => impact,
+                None => continue
+            };
+            paddle.strike(ball, &impact, config);
+            // Curve shots bend back towards the middle of the field
+            ball.curve = -modifiers(side).curve * ball.velocity.y.signum();
+            ball.last_hitter = Some(side);
+            hits[i] += 1;
+            paddle_hits.push((side, impact));
+        }
+
+        if circle {
+            continue;
+        }
+        for bounce in pong.arena.collide(ball, config) {
+            ball.spin = -ball.spin;
+            play_audio(&pong.audio, &pong.boop);
+            pong.recorder.impact(bounce.position.x, bounce.position.y, bounce.direction.x, bounce.direction.y);
+        }
+    }
+
+    pong.doubles.add_hits(hits);
+
+    if multiball::collide_balls(&mut pong.balls, &mut pong.recorder) {
+        play_audio(&pong.audio, &pong.boop);
+    }
+
+    for ball in pong.balls.iter_mut() {
+        if circle {
+            if let Some((top, bounce)) = circle::collide_walls(ball, config) {
+                ball.curve = -ball.curve;
+                ball.spin = -ball.spin;
+                wall_bounces.push((if top { Side::Top } else { Side::Bottom }, bounce));
+            }
+        } else {
+            for wall in [Side::Top, Side::Bottom] {
+                if let Some(bounce) = reflect_off_side(ball, wall, config) {
+                    wall_bounces.push((wall, bounce));
+                }
+            }
+        }
+
+        ball.tail.add(ball.position.clone(), Vec2::zero(), Vec2::zero(), 1000.0);
+        ball.tail.update(delta);
+    }
 
-            ball.position.x = 0.0;
-            ball.velocity.x = (1 - 2 * (timestamp % 2)) as f32;
-            ball.velocity.y = (1 - 2 * ((timestamp/7) % 2)) as f32;
-            play_audio(&pong.bloop);
-            set_score(pong.left_score, pong.right_score);
-        }
-
-    ball.position.y += ball.velocity.y * delta as f32 * BALL_SPEED;
-    if collide(&ball.position, &pong.ball_model.extent,
-               &left.position, &pong.paddle_model.extent) 
-        || collide(&ball.position, &pong.ball_model.extent,
-                   &right.position, &pong.paddle_model.extent) {
-            ball.velocity.y = -ball.velocity.y;
-            ball.position.y += ball.velocity.y * delta as f32 * BALL_SPEED;
-            play_audio(&pong.beep);
-            create_sparks(&mut pong.sparks, ball.position.x,
-                          (if ball.velocity.y > 0. { -1. } else { 1. })
-                          * pong.ball_model.extent.y + ball.position.y,
-                          0., 2. * ball.velocity.y);
-        } else if ball.position.y > 0.95 {
-            ball.velocity.y = -ball.velocity.y.abs();
-            play_audio(&pong.boop);
-            create_sparks(&mut pong.sparks, ball.position.x,
-                          pong.ball_model.extent.y + ball.position.y,
-                          0., 2. * ball.velocity.y);
-        } else if ball.position.y < -0.95 {
-            ball.velocity.y = ball.velocity.y.abs();
-            play_audio(&pong.boop);
-            create_sparks(&mut pong.sparks, ball.position.x,
-                          -pong.ball_model.extent.y + ball.position.y,
-                          0., 2. * ball.velocity.y);
-        }
-
-    pong.ball_tail.add(ball.position.clone(), Vec2::zero(), Vec2::zero(), 1000);
-    pong.ball_tail.update(delta);
+    for (side, impact) in paddle_hits {
+        hit_paddle(pong, side, &impact);
+        if pong.config.multiball && pong.rally.is_multiple_of(multiball::SPAWN_RALLY) {
+            spawns += 1;
+        }
+    }
+    for (side, bounce) in wall_bounces {
+        hit_wall(pong, side, &bounce);
+    }
+    powerup::update(pong, delta, timestamp);
+
+    let config = &pong.config;
+    pong.recorder.create_sparks(&mut pong.sparks, config.sparks_per_hit);
     pong.sparks.update(delta);
+    let paddles = pong.side_paddles();
+    pong.recorder.record(delta, &pong.balls, paddles);
+
+    let scored = take_goals(&mut pong.balls, config);
+    for (scorer, _) in &scored {
+        if *scorer == Side::Left {
+            pong.left_score += 1;
+        } else {
+            pong.right_score += 1;
+        }
+        pong.events.push(GameEvent::PointScored {
+            scorer: *scorer, left_score: pong.left_score, right_score: pong.right_score
+        });
+    }
+
+    let (left_score, right_score) = (pong.left_score, pong.right_score);
+    let winner = if scored.is_empty() { None } else { match_winner(left_score, right_score, config) };
+    if let Some(winner) = winner {
+        pong.events.push(GameEvent::RallyEnded { length: pong.rally });
+        pong.events.push(GameEvent::MatchOver { winner, left_score, right_score });
+        pong.left_score = 0;
+        pong.right_score = 0;
+        pong.balls.clear();
+        if let Some(audio) = &mut pong.audio {
+            audio.music.start(&audio.ctx);
+        }
+    } else if !scored.is_empty() && pong.balls.is_empty() {
+        pong.events.push(GameEvent::RallyEnded { length: pong.rally });
+    }
+
+    // The next serve waits for the last ball in play
+    if let (Some((_, last)), true) = (scored.last(), pong.balls.is_empty()) {
+        pong.rally = 0;
+        pong.spawn_timer = 0.0;
+        let replay = pong.recorder.finish_point(config);
+        if config.replay {
+            pong.replay = Some(replay);
+        }
 
-    pong.ctx.gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
-    pong.ctx.gl.use_program(Some(&pong.ctx.program));
-    pong.ctx.gl.enable_vertex_attrib_array(pong.ctx.position as u32);
-    pong.ctx.gl.enable_vertex_attrib_array(pong.ctx.texcoord as u32);
+        let velocity = multiball::serve_direction(timestamp);
+        pong.events.push(GameEvent::Serve { dx: velocity.x, dy: velocity.y });
+        // Serves in the circle come from the center
+        let y = if config.mode == Mode::Circle { 0.0 } else { last.y };
+        pong.balls.push(Ball::new(Vec2::new(0.0, y), velocity, config.ball_tail_particles));
+        play_audio(&pong.audio, &pong.bloop);
+    }
+    if !scored.is_empty() {
+        pong.scoreboard.set(pong.left_score, pong.right_score);
+    }
 
-    pong.field_model.pre_render(&pong.ctx);
-    pong.field_model.render(&Vec2 {x: 0.0, y: 0.0}, &pong.ctx);
+    for _ in 0..spawns {
+        multiball::spawn(pong, timestamp);
+    }
+}
 
-    pong.ball_tail.render(&pong.ball_tail_model, &pong.ctx);
-    pong.ball_model.pre_render(&pong.ctx);
-    pong.ball_model.render(&ball.position, &pong.ctx);
+// Takes the balls that crossed a goal line out of play, returning who each
+// scored for and where it left. Several balls can score in the same frame.
+fn take_goals(balls: &mut Vec<Ball>, config: &GameConfig) -> Vec<(Side, Vec2)> {
+    let mut scored = Vec::new();
+    balls.retain(|ball| {
+        let out = if config.mode == Mode::Circle {
+            circle::out(ball, config)
+        } else {
+            ball.position.x.abs() > config.goal_line
+        };
+        if out {
+            scored.push((Side::of(-ball.position.x), ball.position.clone()));
+        }
+        !out
+    });
+    scored
+}
 
-    pong.paddle_model.pre_render(&pong.ctx);
-    pong.paddle_model.render(&left.position, &pong.ctx);
-    pong.paddle_model.render(&right.position, &pong.ctx);
+// The winner of a match at this score. A match won by both players at once
+// goes on until one of them leads.
+fn match_winner(left_score: u32, right_score: u32, config: &GameConfig) -> Option<Side> {
+    if left_score.max(right_score) < config.winning_score || left_score == right_score {
+        None
+    } else if left_score > right_score {
+        Some(Side::Left)
+    } else {
+        Some(Side::Right)
+    }
+}
 
-    pong.sparks.render(&pong.spark_model, &pong.ctx);
+// Whether a ball is heading past a paddle that cannot reach it for a goal
+// that ends the match
+fn match_winning_goal_imminent(pong: &Pong) -> bool {
+    pong.config.mode == Mode::Classic && pong.balls.iter().any(|ball| winning_goal_imminent(pong, ball))
+}
 
-    pong.request_animation_frame_handle = request_animation_frame(on_animation_frame);
+fn winning_goal_imminent(pong: &Pong, ball: &Ball) -> bool {
+    let (paddle, scorer_score) = if ball.velocity.x < 0.0 {
+        (&pong.left, pong.right_score)
+    } else {
+        (&pong.right, pong.left_score)
+    };
+    let reach = paddle.shape.extent().y + ball.shape.extent().y;
+    scorer_score + 1 >= pong.config.winning_score
+        && ball.position.x.abs() > pong.config.paddle_x - SLOW_MOTION_DISTANCE
+        && (ball.position.y - paddle.position.y).abs() > reach
 }
 
-pub fn on_key(key: u32, state: bool) {
+fn render(pong: &Pong) {
+    if pong.context_lost {
+        return;
+    }
+
+    pong.ctx.begin_frame();
+
+    pong.models.field.pre_render(&pong.ctx);
+    pong.models.field.render(&Vec2 {x: 0.0, y: 0.0}, &pong.ctx);
+
+    match pong.config.mode {
+        Mode::Classic | Mode::Doubles => pong.arena.render(&pong.arena_graphics, &pong.ctx, &pong.config),
+        Mode::Circle => pong.circle.render(&pong.ctx, &pong.config),
+        Mode::FourPlayer | Mode::Breakout | Mode::Squash | Mode::Practice => ()
+    }
+    if pong.replay.is_none() {
+        pong.powerups.render(&pong.ctx, &pong.config);
+    }
+
+    // Replays are drawn the same way as live play, without the balls' spin
+    let (balls, paddles, sparks): (Vec<(Vec2, f32)>, _, _) = match &pong.replay {
+        Some(replay) => {
+            replay.ball_tail.render(&pong.models.ball_tail, &pong.ctx);
+            let balls = replay.balls.iter().map(|position| (position.clone(), 0.0)).collect();
+            (balls, replay.paddles.clone(), &replay.sparks)
+        },
+        None => {
+            for ball in &pong.balls {
+                ball.tail.render(&pong.models.ball_tail, &pong.ctx);
+            }
+            let balls = pong.balls.iter().map(|ball| (ball.position.clone(), ball.angle)).collect();
+            (balls, pong.side_paddles(), &pong.sparks)
+        }
+    };
+
+    pong.models.ball.pre_render(&pong.ctx);
+    for (position, angle) in &balls {
+        pong.models.ball.render_rotated(position, &Vec2::new(1.0, 1.0), *angle, &pong.ctx);
+    }
+
+    pong.models.paddle.pre_render(&pong.ctx);
+    for (side, position) in &paddles {
+        let (side, position) = (*side, position);
+        let in_play = match pong.config.mode {
+            Mode::FourPlayer => pong.square.alive(side),
+            Mode::Breakout => pong.breakout.side() == side,
+            Mode::Squash | Mode::Practice => side == Side::Left,
+            Mode::Classic | Mode::Circle | Mode::Doubles => true
+        };
+        if !in_play {
+            continue;
+        }
+        let size = Vec2::new(1.0, pong.powerups.modifiers(side).paddle_scale);
+        if pong.config.mode == Mode::Circle {
+            // Paddles on the circle lie along its tangent
+            pong.models.paddle.render_rotated(position, &size, position.y.atan2(position.x), &pong.ctx);
+        } else {
+            pong.models.paddle.render_scaled(position, &size, &pong.ctx);
+        }
+    }
+
+    match pong.config.mode {
+        Mode::FourPlayer => pong.square.render(&pong.models, &pong.ctx, &pong.config),
+        Mode::Breakout => pong.breakout.render(&pong.breakout_graphics, &pong.square.bottom, &pong.models, &pong.ctx),
+        Mode::Squash | Mode::Practice => pong.solo.render(&pong.models, &pong.ctx, &pong.config),
+        Mode::Doubles => pong.doubles.render(&pong.models, &pong.ctx),
+        Mode::Classic | Mode::Circle => ()
+    }
+
+    sparks.render(&pong.models.spark, &pong.ctx);
+
+    if pong.replay.is_some() {
+        let x = -text::TextRenderer::width(REPLAY_LABEL, REPLAY_LABEL_SIZE) / 2.0;
+        pong.models.text.render(&pong.ctx, REPLAY_LABEL, x, 0.9, REPLAY_LABEL_SIZE, 0.8);
+    }
+
+    if pong.status != Status::Running {
+        pong.models.overlay.pre_render(&pong.ctx);
+        pong.models.overlay.render(&Vec2::zero(), &pong.ctx);
+    }
+    if pong.status == Status::Paused {
+        pong.models.pause.pre_render(&pong.ctx);
+        pong.models.pause.render(&Vec2::zero(), &pong.ctx);
+    }
+
+    if pong.debug.enabled {
+        pong.debug.render(pong);
+    }
+    if pong.console.open {
+        pong.console.render(&pong.ctx);
+    }
+}
+
+fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("set", "set <name> [value]", |pong, args| {
+        match args.get(1) {
+            Some(value) => {
+                let mut config = pong.config.clone();
+                config.set(args[0], value).map_err(|e| String::from(e.message()))?;
+                pong.configure(config).map_err(|e| String::from(e.message()))?;
+                Ok(format!("{} = {}", args[0], value))
+            },
+            None => pong.config.get(args[0])
+                .map(|value| format!("{} = {}", args[0], value))
+                .ok_or_else(|| format!("Unknown setting {}", args[0]))
+        }
+    });
+    commands.register("score", "score <left> <right>", |pong, args| {
+        pong.left_score = arg(args[0], "left score")?;
+        pong.right_score = arg(args[1], "right score")?;
+        pong.scoreboard.set(pong.left_score, pong.right_score);
+        Ok(format!("Score {}-{}", pong.left_score, pong.right_score))
+    });
+    ai::register_commands(commands);
+    clock::register_commands(commands);
+    replay::register_commands(commands);
+    multiball::register_commands(commands);
+    arena::register_commands(commands);
+    powerup::register_commands(commands);
+    breakout::register_commands(commands);
+}
+
+fn on_key(pong: &mut Pong, key: u32, state: bool) {
     const KEY_UP: u32 = 38;
     const KEY_DOWN: u32 = 40;
     const KEY_A: u32 = 65;
     const KEY_Z: u32 = 90;
-
-    let pong = unsafe { PONG.as_mut().unwrap() };
+    const KEY_LEFT: u32 = 37;
+    const KEY_RIGHT: u32 = 39;
+    const KEY_G: u32 = 71;
+    const KEY_H: u32 = 72;
+    const KEY_W: u32 = 87;
+    const KEY_S: u32 = 83;
+    const KEY_O: u32 = 79;
+    const KEY_L: u32 = 76;
 
     match key {
         KEY_UP => pong.right.up = state,
         KEY_DOWN => pong.right.down = state,
         KEY_A => pong.left.up = state,
         KEY_Z => pong.left.down = state,
+        KEY_H => pong.square.top.up = state,
+        KEY_G => pong.square.top.down = state,
+        KEY_RIGHT => pong.square.bottom.up = state,
+        KEY_LEFT => pong.square.bottom.down = state,
+        KEY_W => pong.doubles.left.up = state,
+        KEY_S => pong.doubles.left.down = state,
+        KEY_O => pong.doubles.right.up = state,
+        KEY_L => pong.doubles.right.down = state,
         _ => ()
     };
 }
 
+impl Side {
+    fn of(x: f32) -> Side {
+        if x < 0.0 { Side::Left } else { Side::Right }
+    }
+    fn opponent(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+            Side::Top => Side::Bottom,
+            Side::Bottom => Side::Top
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+            Side::Top => "top",
+            Side::Bottom => "bottom"
+        }
+    }
+    // Unit vector from the center towards the side's goal
+    fn outward(self) -> Vec2 {
+        match self {
+            Side::Left => Vec2::new(-1.0, 0.0),
+            Side::Right => Vec2::new(1.0, 0.0),
+            Side::Top => Vec2::new(0.0, 1.0),
+            Side::Bottom => Vec2::new(0.0, -1.0)
+        }
+    }
+}
+
 impl Vec2 {
     fn zero() -> Vec2 {
         Vec2 { x: 0.0, y: 0.0 }
@@ -322,85 +1273,323 @@ impl Vec2 {
     fn new(x: f32, y: f32) -> Vec2 {
         Vec2 { x, y }
     }
+    fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+    fn dot(&self, other: &Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+    // The vector scaled to length 1, or zero for a zero vector
+    fn normalized(&self) -> Vec2 {
+        let length = self.length();
+        if length == 0.0 { Vec2::zero() } else { Vec2::new(self.x / length, self.y / length) }
+    }
+}
+
+impl Ball {
+    fn new(position: Vec2, velocity: Vec2, tail_particles: usize) -> Ball {
+        Ball {
+            position, velocity,
+            shape: BALL_SHAPE,
+            tail: ParticleSystem::new(tail_particles),
+            last_hitter: None,
+            curve: 0.0,
+            spin: 0.0,
+            angle: 0.0
+        }
+    }
+    // Moves the ball for `delta` milliseconds at `speed`, bending its path by
+    // curve shots and spin and letting the spin wear off
+    fn advance(&mut self, delta: f32, speed: f32, config: &GameConfig) {
+        self.velocity.y += self.curve * delta;
+        let magnus = config.magnus * self.spin * delta;
+        let (vx, vy) = (self.velocity.x, self.velocity.y);
+        self.velocity.x -= magnus * vy;
+        self.velocity.y += magnus * vx;
+        self.angle = (self.angle + self.spin * delta) % std::f32::consts::TAU;
+        self.spin *= (-delta / config.spin_decay).exp();
+        self.position.x += self.velocity.x * delta * speed;
+        self.position.y += self.velocity.y * delta * speed;
+    }
+    fn limit_speed(&mut self) {
+        let speed = self.velocity.length();
+        if speed > MAX_BALL_SPEED {
+            self.velocity.x *= MAX_BALL_SPEED / speed;
+            self.velocity.y *= MAX_BALL_SPEED / speed;
+        }
+    }
+    // Spins the ball up after it bounced off a surface facing `normal` and
+    // moving at `velocity`, which drags the side of the ball it touches along
+    fn add_spin(&mut self, normal: &Vec2, velocity: &Vec2, config: &GameConfig) {
+        let radius = self.shape.extent().x;
+        let drag = normal.y * velocity.x - normal.x * velocity.y;
+        self.spin = -self.spin + config.spin * drag / radius;
+    }
+}
+
+impl Paddle {
+    fn new(position: Vec2, track: Track, shape: Shape) -> Paddle {
+        let mut paddle = Paddle {
+            position, track, shape, zone: None, velocity: Vec2::zero(), inertia: None, speed: 0.0,
+            ai: None, up: false, down: false
+        };
+        paddle.set_shape(shape);
+        paddle
+    }
+    // Coordinate of `v` along the paddle's track
+    fn along(&self, v: &Vec2) -> f32 {
+        match self.track {
+            Track::Vertical => v.y,
+            Track::Horizontal => v.x,
+            Track::Arc { radius, home } => circle::offset(v, home) * radius
+        }
+    }
+    // Coordinate of `v` across the paddle's track, towards its goal
+    fn across(&self, v: &Vec2) -> f32 {
+        match self.track {
+            Track::Vertical => v.x,
+            Track::Horizontal => v.y,
+            Track::Arc { .. } => v.length()
+        }
+    }
+    // -1, 0 or 1 for the direction the paddle's inputs move it in
+    fn direction(&self) -> f32 {
+        if self.up == self.down { 0. } else if self.up { 1. } else { -1. }
+    }
+    fn slide(&mut self, distance: f32, limit: f32) {
+        let (low, high) = self.zone.unwrap_or((-limit, limit));
+        let position = match self.track {
+            Track::Vertical => &mut self.position.y,
+            Track::Horizontal => &mut self.position.x,
+            Track::Arc { radius, home } => {
+                let along = clamp(self.along(&self.position) + distance, low, high);
+                self.position = circle::point(radius, home, along / radius);
+                return;
+            }
+        };
+        *position = clamp(*position + distance, low, high);
+    }
+    // Slides the paddle in `direction` for `delta` milliseconds, at `speed` or
+    // as its inertia allows, keeping track of how fast it actually moved
+    fn steer(&mut self, direction: f32, speed: f32, delta: f32, limit: f32) {
+        let before = self.position.clone();
+        let inertia = match self.inertia {
+            Some(inertia) => inertia,
+            None => {
+                self.slide(direction * speed * delta, limit);
+                self.velocity = Paddle::moved(&before, &self.position, delta);
+                return;
+            }
+        };
+        self.speed *= (1.0 - inertia.friction).powf(delta);
+        self.speed = clamp(self.speed + direction * inertia.acceleration * delta,
+                           -inertia.max_speed, inertia.max_speed);
+        self.slide(self.speed * delta, limit);
+        self.velocity = Paddle::moved(&before, &self.position, delta);
+
+        // Paddles reaching the end of their zone bounce back
+        let (low, high) = self.zone.unwrap_or((-limit, limit));
+        let along = self.along(&self.position);
+        let stopped = if self.speed < 0.0 { along <= low + LIMIT_TOLERANCE } else { along >= high - LIMIT_TOLERANCE };
+        if self.speed != 0.0 && stopped {
+            self.speed = -self.speed * inertia.bounce;
+        }
+    }
+    fn moved(before: &Vec2, after: &Vec2, delta: f32) -> Vec2 {
+        if delta > 0.0 {
+            Vec2::new((after.x - before.x) / delta, (after.y - before.y) / delta)
+        } else {
+            Vec2::zero()
+        }
+    }
+    // Spins a ball that just bounced off the paddle and, with inertia, passes
+    // some of the paddle's speed on to it
+    fn strike(&self, ball: &mut Ball, impact: &Bounce, config: &GameConfig) {
+        ball.add_spin(&impact.direction.normalized(), &self.velocity, config);
+        if let Some(inertia) = self.inertia {
+            // Ball velocities are in multiples of `ball_speed`
+            ball.velocity.x += self.velocity.x * inertia.transfer / config.ball_speed;
+            ball.velocity.y += self.velocity.y * inertia.transfer / config.ball_speed;
+            ball.limit_speed();
+        }
+    }
+    // Sets the shape of a vertical paddle, turning it for horizontal tracks
+    fn set_shape(&mut self, shape: Shape) {
+        self.shape = match self.track {
+            Track::Vertical | Track::Arc { .. } => shape,
+            Track::Horizontal => shape.transposed()
+        };
+    }
+    // Where the ball touches the paddle, if it does
+    fn contact(&self, ball: &Ball) -> Option<collision::Contact> {
+        match self.track {
+            Track::Arc { radius, .. } => circle::collide_paddle(ball, self, radius),
+            _ => collision::collide(&ball.position, &ball.shape, &self.position, &self.shape)
+        }
+    }
 }
 
 impl RenderContext {
-    fn new(gl: WebGlRenderingContext, program: WebGlProgram) -> RenderContext {
-        let position = gl.get_attrib_location(&program, "a_position");
-        let texcoord = gl.get_attrib_location(&program, "a_texcoord");
-        let offset = gl.get_uniform_location(&program, "u_offset").unwrap();
-        let sampler = gl.get_uniform_location(&program, "u_sampler").unwrap();
-        let opacity = gl.get_uniform_location(&program, "u_opacity").unwrap();
-        RenderContext {
+    fn init(gl: Gl) -> Result<RenderContext, PongError> {
+        gl.clear_color(0.1, 0.1, 0.1, 1.0);
+        gl.enable(GL::DEPTH_TEST);
+        gl.enable(GL::BLEND);
+        gl.depth_func(GL::LEQUAL);
+        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+
+        let (vertex_source, fragment_source) = match gl {
+            Gl::WebGl1(_) => (data::VERTEX_SHADER, data::FRAGMENT_SHADER),
+            Gl::WebGl2(_) => (data::VERTEX_SHADER_300, data::FRAGMENT_SHADER_300)
+        };
+        let vert_shader = compile_shader(&gl, ShaderStage::Vertex, vertex_source)?;
+        let frag_shader = compile_shader(&gl, ShaderStage::Fragment, fragment_source)?;
+        let program = link_program(&gl, &vert_shader, &frag_shader)?;
+        gl.use_program(Some(&program));
+
+        RenderContext::new(gl, program)
+    }
+    fn new(gl: Gl, program: WebGlProgram) -> Result<RenderContext, PongError> {
+        let uniform = |name: &str| gl.get_uniform_location(&program, name)
+            .ok_or_else(|| PongError::WebGl(format!("Missing shader uniform {}", name)));
+        let attribute = |name: &str| match gl.get_attrib_location(&program, name) {
+            -1 => Err(PongError::WebGl(format!("Missing shader attribute {}", name))),
+            location => Ok(location as u32)
+        };
+        let position = attribute("a_position")?;
+        let texcoord = attribute("a_texcoord")?;
+        let offset = uniform("u_offset")?;
+        let size = uniform("u_size")?;
+        let rotation = uniform("u_rotation")?;
+        let sampler = uniform("u_sampler")?;
+        let opacity = uniform("u_opacity")?;
+        let frame = match &gl {
+            Gl::WebGl1(_) => FrameUniforms::Uniforms { scale: uniform("u_scale")? },
+            Gl::WebGl2(gl) => {
+                let index = gl.get_uniform_block_index(&program, "Frame");
+                if index == WebGl2RenderingContext::INVALID_INDEX {
+                    return Err(PongError::WebGl(String::from("Missing shader uniform block Frame")));
+                }
+                let buffer = gl.create_buffer()
+                    .ok_or_else(|| PongError::WebGl(String::from("Unable to create uniform buffer")))?;
+                gl.uniform_block_binding(&program, index, FRAME_BINDING);
+                gl.bind_buffer_base(WebGl2RenderingContext::UNIFORM_BUFFER, FRAME_BINDING, Some(&buffer));
+                FrameUniforms::Block(buffer)
+            }
+        };
+        Ok(RenderContext {
             gl, program, position, texcoord,
-            offset, sampler, opacity
+            offset, size, rotation, sampler, opacity, frame,
+            draw_calls: Cell::new(0)
+        })
+    }
+    // Matches the viewport to the canvas and scales the square playing field to
+    // fit inside it, then clears the screen and selects the shader program
+    fn begin_frame(&self) {
+        let width = self.gl.drawing_buffer_width();
+        let height = self.gl.drawing_buffer_height().max(1);
+        let aspect = width as f32 / height as f32;
+        let (scale_x, scale_y) = if aspect > 1.0 { (1.0 / aspect, 1.0) } else { (1.0, aspect) };
+
+        self.draw_calls.set(0);
+        self.gl.viewport(0, 0, width, height);
+        self.gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        self.gl.use_program(Some(&self.program));
+        self.gl.uniform2f(Some(&self.size), 1.0, 1.0);
+        self.gl.uniform2f(Some(&self.rotation), 1.0, 0.0);
+        match &self.frame {
+            FrameUniforms::Uniforms { scale } => {
+                self.gl.uniform2f(Some(scale), scale_x, scale_y);
+                self.gl.enable_vertex_attrib_array(self.position);
+                self.gl.enable_vertex_attrib_array(self.texcoord);
+            },
+            FrameUniforms::Block(buffer) => {
+                // std140 pads the block to a whole vec4
+                let frame = js_sys::Float32Array::from(&[scale_x, scale_y, 0.0, 0.0][..]);
+                self.gl.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(buffer));
+                self.gl.buffer_data_with_array_buffer_view(WebGl2RenderingContext::UNIFORM_BUFFER,
+                                                           &frame, GL::DYNAMIC_DRAW);
+            }
         }
     }
-    fn load_texture(&self, data: &[u8], width: i32, height: i32) -> WebGlTexture {
-        let texture = self.gl.create_texture().unwrap();
+    fn draw(&self, vertex_count: i32) {
+        self.draw_calls.set(self.draw_calls.get() + 1);
+        self.gl.draw_arrays(GL::TRIANGLES, 0, vertex_count);
+    }
+    fn load_texture(&self, data: &[u8], width: i32, height: i32) -> Result<WebGlTexture, PongError> {
+        let texture = self.gl.create_texture()
+            .ok_or_else(|| PongError::WebGl(String::from("Unable to create texture")))?;
         self.gl.active_texture(GL::TEXTURE0);
         self.gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
         self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
             GL::TEXTURE_2D, 0, GL::RGBA as i32,
             width, height, 0, GL::RGBA, 
             GL::UNSIGNED_BYTE,
-            Some(data)).unwrap();
+            Some(data))
+            .map_err(|e| PongError::WebGl(format!("Unable to load texture: {}", describe(e))))?;
         self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::NEAREST as i32);
         self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::NEAREST as i32);
-        texture
+        Ok(texture)
     }
 }
 
-impl Model {
-    fn new(ctx: &RenderContext, vertices: &[f32], texture: WebGlTexture) -> Model {
-        let vertex_buffer = ctx.gl.create_buffer().unwrap();
-        ctx.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
-
-        // FIXME: Hack to fill a vertex buffer
-        let memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>().unwrap()
-            .buffer();
-        let vertices_location = vertices.as_ptr() as u32 / 4;
-        let vertex_array = js_sys::Float32Array::new(&memory_buffer)
-            .subarray(vertices_location, vertices_location + vertices.len() as u32);
-
-        ctx.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
-        ctx.gl.buffer_data_with_array_buffer_view(WebGlRenderingContext::ARRAY_BUFFER,
-                                                  &vertex_array,
-                                                  WebGlRenderingContext::STATIC_DRAW);
-
-        let mut x: f32 = 0.0;
-        let mut y: f32 = 0.0;
+impl Models {
+    fn new(ctx: &RenderContext) -> Result<Models, PongError> {
+        let model = |vertices: &[f32], texture: &[u8], size: i32| {
+            Model::new(ctx, vertices, ctx.load_texture(texture, size, size)?)
+        };
+        Ok(Models {
+            ball: model(&data::BALL_VERTICES, &data::BALL_TEXTURE, 4)?,
+            ball_tail: model(&data::BALL_TAIL_VERTICES, &data::BALL_TAIL_TEXTURE, 4)?,
+            paddle: model(&data::PADDLE_VERTICES, &data::PADDLE_TEXTURE, 8)?,
+            spark: model(&data::SPARK_VERTICES, &data::SPARK_TEXTURE, 4)?,
+            field: model(&data::FIELD_VERTICES, &data::FIELD_TEXTURE, 8)?,
+            overlay: model(&data::OVERLAY_VERTICES, &data::OVERLAY_TEXTURE, 1)?,
+            pause: model(&data::PAUSE_VERTICES, &data::PAUSE_TEXTURE, 1)?,
+            text: text::TextRenderer::new(ctx)?
+        })
+    }
+}
 
-        for xs in vertices.chunks(4) {
-            x = x.max(xs.get(0).unwrap().abs());
-            y = y.max(xs.get(1).unwrap().abs());
-        }
+impl Model {
+    fn new(ctx: &RenderContext, vertices: &[f32], texture: WebGlTexture) -> Result<Model, PongError> {
+        let vertex_array = VertexArray::new(ctx)?;
+        vertex_array.upload(ctx, vertices, GL::STATIC_DRAW);
 
-        Model {
-            vertex_buffer,
+        Ok(Model {
+            vertices: vertex_array,
             num_vertices: vertices.len() as u32,
-            texture,
-            extent: Vec2 { x: x * 0.9, y: y * 0.9 }
-        }
+            texture
+        })
     }
     fn pre_render(&self, ctx: &RenderContext) {
-        ctx.gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.vertex_buffer));
+        self.vertices.bind(ctx);
         ctx.gl.active_texture(GL::TEXTURE0);
         ctx.gl.bind_texture(GL::TEXTURE_2D, Some(&self.texture));
-        ctx.gl.vertex_attrib_pointer_with_i32(ctx.position as u32, 2, GL::FLOAT, false, 16, 0);
-        ctx.gl.vertex_attrib_pointer_with_i32(ctx.texcoord as u32, 2, GL::FLOAT, false, 16, 8);
         ctx.gl.uniform1i(Some(&ctx.sampler), 0);
         ctx.gl.uniform1f(Some(&ctx.opacity), 1.0);
     }
     fn render(&self, pos: &Vec2, ctx: &RenderContext) {
         ctx.gl.uniform4f(Some(&ctx.offset), pos.x, pos.y, 0.0, 0.0);
-        ctx.gl.draw_arrays(GL::TRIANGLES, 0, self.num_vertices as i32 / 4);
+        ctx.draw(self.num_vertices as i32 / 4);
+    }
+    // Draws the model stretched by `size`, e.g. for a paddle grown by a power-up
+    fn render_scaled(&self, pos: &Vec2, size: &Vec2, ctx: &RenderContext) {
+        ctx.gl.uniform2f(Some(&ctx.size), size.x, size.y);
+        self.render(pos, ctx);
+        ctx.gl.uniform2f(Some(&ctx.size), 1.0, 1.0);
+    }
+    // Draws the model stretched by `size` and turned counterclockwise by
+    // `angle` radians around its center
+    fn render_rotated(&self, pos: &Vec2, size: &Vec2, angle: f32, ctx: &RenderContext) {
+        ctx.gl.uniform2f(Some(&ctx.rotation), angle.cos(), angle.sin());
+        self.render_scaled(pos, size, ctx);
+        ctx.gl.uniform2f(Some(&ctx.rotation), 1.0, 0.0);
     }
     fn render_particle(&self, pos: &Vec2, opacity: f32, ctx: &RenderContext) {
         ctx.gl.uniform4f(Some(&ctx.offset), pos.x, pos.y, 0.0, 0.0);
         ctx.gl.uniform1f(Some(&ctx.opacity), opacity);
-        ctx.gl.draw_arrays(GL::TRIANGLES, 0, self.num_vertices as i32 / 4);
+        ctx.draw(self.num_vertices as i32 / 4);
     }
 }
 
@@ -417,103 +1606,349 @@ impl ParticleSystem {
         model.pre_render(ctx);
         for particle in self.particles.iter() {
             model.render_particle(&particle.position, 
-                                  particle.life as f32 / particle.total_life as f32,
+                                  particle.life / particle.total_life,
                                   ctx);
         }
     }
-    fn add(&mut self, position: Vec2, velocity: Vec2, acceleration: Vec2, life: i32) {
+    fn add(&mut self, position: Vec2, velocity: Vec2, acceleration: Vec2, life: f32) {
         if self.particles.len() < self.max_particles {
             self.particles.push(Particle { position, velocity, acceleration, life, total_life: life });
         }
     }
     fn update(&mut self, delta: f32) {
         for p in self.particles.iter_mut() {
-            p.life -= delta as i32;
+            p.life -= delta;
             p.velocity.x += p.acceleration.x * delta / 1000.0;
             p.velocity.y += p.acceleration.y * delta / 1000.0;
             p.position.x += p.velocity.x * delta / 1000.0;
             p.position.y += p.velocity.y * delta / 1000.0;
         }
-        self.particles.retain(|p| p.life > 0);
+        self.particles.retain(|p| p.life > 0.0);
     }
 }
 
-fn compile_shader(ctx: &WebGlRenderingContext, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
-    let shader = ctx.create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
-    ctx.shader_source(&shader, source);
-    ctx.compile_shader(&shader);
-
-    let ok = ctx.get_shader_parameter(&shader, GL::COMPILE_STATUS)
-            .as_bool()
-            .unwrap_or(false);
-    if ok {
-        Ok(shader)
-    } else {
-        Err(ctx.get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+// Reflects the ball off a contact it is moving into, scaling the rebound by
+// `restitution`
+fn bounce(ball: &mut Ball, contact: collision::Contact, restitution: f32) -> Option<Bounce> {
+    let normal = contact.normal;
+    // Separating contacts are left alone, so nothing is pulled back through
+    let approach = ball.velocity.x * normal.x + ball.velocity.y * normal.y;
+    if approach >= 0.0 {
+        return None;
     }
+    ball.position.x += normal.x * contact.depth;
+    ball.position.y += normal.y * contact.depth;
+    ball.velocity.x -= (1.0 + restitution) * approach * normal.x;
+    ball.velocity.y -= (1.0 + restitution) * approach * normal.y;
+    ball.limit_speed();
+    let radius = ball.shape.extent().x;
+    Some(Bounce {
+        position: Vec2::new(ball.position.x - normal.x * radius, ball.position.y - normal.y * radius),
+        direction: Vec2::new(-2.0 * approach * normal.x, -2.0 * approach * normal.y)
+    })
 }
 
-fn link_program(ctx: &WebGlRenderingContext, vert_shader: &WebGlShader, frag_shader: &WebGlShader) -> Result<WebGlProgram, String> {
-    let program = ctx.create_program().ok_or_else(|| String::from("Unable to create shader object"))?;
+// Bounces a ball moving out past the closed `side` of the field back in
+fn reflect_off_side(ball: &mut Ball, side: Side, config: &GameConfig) -> Option<Bounce> {
+    let outward = side.outward();
+    let speed = ball.velocity.dot(&outward);
+    if ball.position.dot(&outward) <= config.wall || speed <= 0.0 {
+        return None;
+    }
+    ball.velocity.x -= 2.0 * speed * outward.x;
+    ball.velocity.y -= 2.0 * speed * outward.y;
+    ball.spin = -ball.spin;
+    // Curve shots bend back towards the middle after the top and bottom walls
+    if outward.x == 0.0 {
+        ball.curve = -ball.curve;
+    }
+    let radius = ball.shape.extent().x;
+    Some(Bounce {
+        position: Vec2::new(ball.position.x + outward.x * radius, ball.position.y + outward.y * radius),
+        direction: Vec2::new(-2.0 * speed * outward.x, -2.0 * speed * outward.y)
+    })
+}
 
-    ctx.attach_shader(&program, vert_shader);
-    ctx.attach_shader(&program, frag_shader);
-    ctx.link_program(&program);
+// Counts a paddle hit on `side` towards the rally, with the hit stop, sound
+// and sparks every mode gives it
+fn hit_paddle(pong: &mut Pong, side: Side, impact: &Bounce) {
+    pong.rally += 1;
+    pong.events.push(GameEvent::PaddleHit { side, rally: pong.rally });
+    pong.clock.hit_stop(pong.config.hit_stop);
+    play_audio(&pong.audio, &pong.beep);
+    pong.recorder.impact(impact.position.x, impact.position.y, impact.direction.x, impact.direction.y);
+}
 
-    let ok = ctx.get_program_parameter(&program, GL::LINK_STATUS)
-        .as_bool()
-        .unwrap_or(false);
-    if ok {
-        Ok(program)
-    } else {
-        Err(ctx.get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+// Sound and sparks for a ball bouncing off the wall on `side`, reported as a
+// wall bounce for the top and bottom walls
+fn hit_wall(pong: &mut Pong, side: Side, bounce: &Bounce) {
+    if side == Side::Top || side == Side::Bottom {
+        pong.events.push(GameEvent::WallBounce { top: side == Side::Top });
     }
+    play_audio(&pong.audio, &pong.boop);
+    pong.recorder.impact(bounce.position.x, bounce.position.y, bounce.direction.x, bounce.direction.y);
 }
-fn collide(p1: &Vec2, e1: &Vec2, p2: &Vec2, e2: &Vec2) -> bool {
-    (if p1.x < p2.x { p2.x - p1.x } else { p1.x - p2.x }) < e1.x + e2.x &&
-        (if p1.y < p2.y { p2.y - p1.y } else { p1.y - p2.y }) < e1.y + e2.y
+
+fn paddle_shape(config: &GameConfig) -> Shape {
+    if config.rounded_paddles { ROUNDED_PADDLE_SHAPE } else { PADDLE_SHAPE }
+}
+
+fn paddle_track(config: &GameConfig, side: Side) -> Track {
+    match config.mode {
+        Mode::Circle => circle::track(config, side),
+        _ => Track::Vertical
+    }
 }
 
 fn clamp(x: f32, min: f32, max: f32) -> f32 {
     x.max(min).min(max)
 }
 
-fn play_audio(sample: &[f32]) {
-    let pong = unsafe { PONG.as_mut().unwrap() };
-    let ctx = &pong.audio_ctx;
-    let buffer = &pong.audio_buffer;
-
-    let source = ctx.create_buffer_source().unwrap();
-
-    // FIXME: copy_to_channel requires a mutable reference for some reason
-    let mut_sample = unsafe {(sample as *const [f32] as *mut [f32]).as_mut().unwrap()};
+impl Audio {
+    fn new() -> Result<Audio, PongError> {
+        let ctx = AudioContext::new()
+            .map_err(|e| PongError::Audio(format!("Unable to create audio context: {}", describe(e))))?;
+        let buffer = ctx.create_buffer(1, (ctx.sample_rate() * 2.0) as u32, ctx.sample_rate())
+            .map_err(|e| PongError::Audio(format!("Unable to create audio buffer: {}", describe(e))))?;
+        let mut music = music::Sequencer::new(&ctx)?;
+        music.start(&ctx);
+        Ok(Audio { ctx, buffer, music })
+    }
+    fn play(&self, sample: &[f32]) -> Result<(), JsValue> {
+        let source = self.ctx.create_buffer_source()?;
+
+        self.buffer.copy_to_channel(sample, 0)?;
+        source.set_buffer(Some(&self.buffer));
+        source.connect_with_audio_node(&self.ctx.destination())?;
+        let _ = self.ctx.resume()?;
+        source.start()
+    }
+    fn update_music(&mut self, intensity: &music::Intensity) {
+        if let Err(e) = self.music.update(&self.ctx, intensity) {
+            gloo::console::warn!(e.to_string());
+            self.music.stop();
+        }
+    }
+}
 
-    buffer.copy_to_channel(mut_sample, 0).unwrap();
-    source.set_buffer(Some(&buffer));
-    source.connect_with_audio_node(&ctx.destination()).unwrap();
-    ctx.resume().unwrap();
-    source.start().unwrap();
+fn play_audio(audio: &Option<Audio>, sample: &[f32]) {
+    if let Some(audio) = audio {
+        if let Err(e) = audio.play(sample) {
+            gloo::console::warn!("Unable to play sound:", e);
+        }
+    }
 }
 
-fn set_score(left: u32, right: u32) {
-    let document = web_sys::window().unwrap().document().unwrap();
-    document.get_element_by_id("score_left").unwrap()
-        .set_text_content(Some(&left.to_string()));
-    document.get_element_by_id("score_right").unwrap()
-        .set_text_content(Some(&right.to_string()));
+impl Scoreboard {
+    fn new(canvas: &HtmlCanvasElement) -> Scoreboard {
+        let find = |class: &str| canvas.parent_element()
+            .and_then(|parent| parent.query_selector(class).ok().flatten());
+        Scoreboard {
+            left: find(".score_left"),
+            right: find(".score_right")
+        }
+    }
+    fn set(&self, left: u32, right: u32) {
+        if let Some(element) = &self.left {
+            element.set_text_content(Some(&left.to_string()));
+        }
+        if let Some(element) = &self.right {
+            element.set_text_content(Some(&right.to_string()));
+        }
+    }
 }
 
-fn create_sparks(ps: &mut ParticleSystem, x: f32, y: f32, dx: f32, dy: f32) {
-    for i in 0..4 {
+fn create_sparks(ps: &mut ParticleSystem, count: usize, x: f32, y: f32, dx: f32, dy: f32) {
+    for i in 0..count / 2 {
         let i = i as f32;
         let ddx = (i + 1.0) * dx / 10.0;
         let ddy = (i + 1.0) * dy / 10.0;
-        ps.add(Vec2::new(x, y), Vec2::new(dy + ddx, -dx + ddy), Vec2::zero(), 100);
-        ps.add(Vec2::new(x, y), Vec2::new(-dy + ddx, dx + ddy), Vec2::zero(), 100);
+        ps.add(Vec2::new(x, y), Vec2::new(dy + ddx, -dx + ddy), Vec2::zero(), 100.0);
+        ps.add(Vec2::new(x, y), Vec2::new(-dy + ddx, dx + ddy), Vec2::zero(), 100.0);
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(x: f32, y: f32, dx: f32, dy: f32) -> Ball {
+        Ball::new(Vec2::new(x, y), Vec2::new(dx, dy), 0)
+    }
 
+    #[test]
+    fn spin_curves_the_ball_to_the_left_of_its_path() {
+        let config = GameConfig::default();
+        for direction in [1.0, -1.0] {
+            let mut counterclockwise = ball(0.0, 0.0, direction, 0.0);
+            counterclockwise.spin = 0.01;
+            let mut clockwise = ball(0.0, 0.0, direction, 0.0);
+            clockwise.spin = -0.01;
+            for _ in 0..10 {
+                counterclockwise.advance(16.0, config.ball_speed, &config);
+                clockwise.advance(16.0, config.ball_speed, &config);
+            }
+            assert!(counterclockwise.velocity.y * direction > 0.0);
+            assert!(counterclockwise.position.y * direction > 0.0);
+            assert!(clockwise.velocity.y * direction < 0.0);
+            assert!(counterclockwise.angle > 0.0 && clockwise.angle < 0.0);
+        }
+    }
+
+    #[test]
+    fn spin_decays_to_a_third_after_spin_decay() {
+        let config = GameConfig::default();
+        let mut ball = ball(0.0, 0.0, 1.0, 0.0);
+        ball.spin = 0.01;
+        let steps = 100;
+        for _ in 0..steps {
+            ball.advance(config.spin_decay / steps as f32, config.ball_speed, &config);
+        }
+        assert!((ball.spin - 0.01 / std::f32::consts::E).abs() < 1e-5, "spin {}", ball.spin);
+    }
+
+    #[test]
+    fn moving_paddles_spin_the_ball_against_their_motion() {
+        let config = GameConfig::default();
+        let mut paddle = Paddle::new(Vec2::new(-config.paddle_x, 0.0), Track::Vertical, PADDLE_SHAPE);
+        paddle.velocity = Vec2::new(0.0, config.paddle_speed);
+        let impact = Bounce { position: Vec2::new(-0.85, 0.0), direction: Vec2::new(2.0, 0.0) };
+
+        // Dragging the left side of the ball up turns it clockwise
+        let mut ball = ball(-0.8, 0.0, 1.0, 0.0);
+        paddle.strike(&mut ball, &impact, &config);
+        let expected = -config.spin * config.paddle_speed / BALL_SHAPE.extent().x;
+        assert!((ball.spin - expected).abs() < 1e-7, "spin {}", ball.spin);
+
+        // A still paddle only turns the ball's spin around
+        paddle.velocity = Vec2::zero();
+        ball.spin = 0.01;
+        paddle.strike(&mut ball, &impact, &config);
+        assert_eq!(ball.spin, -0.01);
+    }
+
+    #[test]
+    fn walls_reverse_spin() {
+        let config = GameConfig::default();
+        let mut ball = ball(0.0, config.wall + 0.01, 1.0, 1.0);
+        ball.spin = 0.01;
+        let bounce = reflect_off_side(&mut ball, Side::Top, &config).expect("ball should bounce");
+        assert_eq!(ball.spin, -0.01);
+        assert_eq!(ball.velocity.y, -1.0);
+        assert!(bounce.direction.y < 0.0);
+        // Balls already heading back in are left alone
+        assert!(reflect_off_side(&mut ball, Side::Top, &config).is_none());
+        assert_eq!(ball.spin, -0.01);
+    }
+
+    fn inertia_paddle(inertia: Inertia) -> Paddle {
+        let mut paddle = Paddle::new(Vec2::new(-0.9, 0.0), Track::Vertical, PADDLE_SHAPE);
+        paddle.inertia = Some(inertia);
+        paddle
+    }
+
+    #[test]
+    fn inertia_accelerates_up_to_max_speed() {
+        let inertia = Inertia { friction: 0.0, ..Inertia::default() };
+        let mut paddle = inertia_paddle(inertia);
+        paddle.steer(1.0, 0.0, 10.0, 0.8);
+        assert!((paddle.speed - inertia.acceleration * 10.0).abs() < 1e-9);
+        assert!((paddle.position.y - paddle.speed * 10.0).abs() < 1e-7);
+        for _ in 0..100 {
+            paddle.position.y = 0.0;
+            paddle.steer(1.0, 0.0, 10.0, 0.8);
+            assert!(paddle.speed <= inertia.max_speed);
+        }
+        assert_eq!(paddle.speed, inertia.max_speed);
+        assert!((paddle.velocity.y - inertia.max_speed).abs() < 1e-7);
+    }
+
+    #[test]
+    fn friction_slows_a_coasting_paddle() {
+        let inertia = Inertia::default();
+        let mut paddle = inertia_paddle(inertia);
+        paddle.speed = 0.001;
+        paddle.steer(0.0, 0.0, 10.0, 0.8);
+        let expected = 0.001 * (1.0 - inertia.friction).powf(10.0);
+        assert!((paddle.speed - expected).abs() < 1e-9);
+        assert!((paddle.position.y - expected * 10.0).abs() < 1e-7);
+    }
+
+    #[test]
+    fn inertia_bounces_off_the_ends_of_the_track() {
+        let inertia = Inertia { friction: 0.0, ..Inertia::default() };
+        let mut paddle = inertia_paddle(inertia);
+        paddle.position.y = 0.79;
+        paddle.speed = inertia.max_speed;
+        paddle.steer(0.0, 0.0, 10.0, 0.8);
+        assert_eq!(paddle.position.y, 0.8);
+        assert!((paddle.speed + inertia.max_speed * inertia.bounce).abs() < 1e-9);
+
+        // Zones end the track early
+        paddle.zone = Some((0.2, 0.8));
+        paddle.position.y = 0.21;
+        paddle.speed = -inertia.max_speed;
+        paddle.steer(0.0, 0.0, 10.0, 0.8);
+        assert_eq!(paddle.position.y, 0.2);
+        assert!((paddle.speed - inertia.max_speed * inertia.bounce).abs() < 1e-9);
+    }
+
+    #[test]
+    fn inertia_passes_paddle_velocity_to_the_ball() {
+        let config = GameConfig::default();
+        let inertia = Inertia::default();
+        let mut paddle = inertia_paddle(inertia);
+        paddle.velocity = Vec2::new(0.0, config.ball_speed);
+        let impact = Bounce { position: Vec2::new(-0.85, 0.0), direction: Vec2::new(2.0, 0.0) };
+        let mut ball = ball(-0.8, 0.0, 1.0, 0.0);
+        paddle.strike(&mut ball, &impact, &config);
+        assert_eq!(ball.velocity.x, 1.0);
+        assert!((ball.velocity.y - inertia.transfer).abs() < 1e-6);
+    }
+
+    #[test]
+    fn paddles_without_inertia_move_at_constant_speed() {
+        let config = GameConfig::default();
+        let mut paddle = Paddle::new(Vec2::new(-0.9, 0.0), Track::Vertical, PADDLE_SHAPE);
+        paddle.steer(1.0, config.paddle_speed, 10.0, 0.8);
+        assert!((paddle.position.y - config.paddle_speed * 10.0).abs() < 1e-7);
+        assert!((paddle.velocity.y - config.paddle_speed).abs() < 1e-7);
+        paddle.steer(0.0, config.paddle_speed, 10.0, 0.8);
+        assert_eq!(paddle.velocity.y, 0.0);
+        assert_eq!(paddle.speed, 0.0);
+
+        let impact = Bounce { position: Vec2::new(-0.85, 0.0), direction: Vec2::new(2.0, 0.0) };
+        paddle.velocity = Vec2::new(0.0, config.paddle_speed);
+        let mut ball = ball(-0.8, 0.0, 1.0, 0.0);
+        paddle.strike(&mut ball, &impact, &config);
+        assert_eq!(ball.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn every_ball_crossing_a_goal_line_scores() {
+        let config = GameConfig::default();
+        let mut balls = vec![ball(1.1, 0.3, 1.0, 1.0), ball(0.0, 0.0, 1.0, 1.0), ball(-1.2, -0.2, -1.0, 1.0)];
+        let scored = take_goals(&mut balls, &config);
+        assert!(scored.iter().map(|(side, _)| *side).eq([Side::Left, Side::Right]));
+        assert_eq!((scored[0].1.x, scored[0].1.y), (1.1, 0.3));
+
+        // The serve waits for the ball still in play
+        assert_eq!(balls.len(), 1);
+        assert!(take_goals(&mut balls, &config).is_empty());
+        balls[0].position.x = -1.1;
+        assert!(take_goals(&mut balls, &config).iter().map(|(side, _)| *side).eq([Side::Right]));
+        assert!(balls.is_empty());
+    }
+
+    #[test]
+    fn matches_are_won_by_a_lead_at_the_winning_score() {
+        let config = GameConfig { winning_score: 3, ..GameConfig::default() };
+        assert!(match_winner(2, 1, &config).is_none());
+        assert!(match_winner(3, 1, &config) == Some(Side::Left));
+        assert!(match_winner(2, 3, &config) == Some(Side::Right));
+
+        // Two balls taking both players to the winning score extend the match
+        assert!(match_winner(3, 3, &config).is_none());
+        assert!(match_winner(4, 3, &config) == Some(Side::Left));
+    }
+}
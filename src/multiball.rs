@@ -0,0 +1,113 @@
+use crate::{Ball, Pong, Vec2};
+use crate::collision;
+use crate::command::Commands;
+use crate::events::GameEvent;
+use crate::replay::Recorder;
+
+// In multiball matches every this many rally hits adds a ball
+pub const SPAWN_RALLY: u32 = 8;
+
+pub fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("spawn_ball", "spawn_ball", |pong, _| {
+        if spawn(pong, pong.timestamp) {
+            Ok(format!("{} balls", pong.balls.len()))
+        } else {
+            Err(format!("There are already {} balls", pong.config.max_balls))
+        }
+    });
+}
+
+// Adds a ball at the center unless there are already `max_balls`
+pub fn spawn(pong: &mut Pong, seed: i32) -> bool {
+    let velocity = serve_direction(seed + pong.balls.len() as i32);
+    add(pong, Vec2::zero(), velocity)
+}
+
+pub fn add(pong: &mut Pong, position: Vec2, velocity: Vec2) -> bool {
+    if pong.balls.len() >= pong.config.max_balls {
+        return false;
+    }
+    pong.events.push(GameEvent::BallSpawned {
+        dx: velocity.x, dy: velocity.y, balls: pong.balls.len() as u32 + 1
+    });
+    pong.balls.push(Ball::new(position, velocity, pong.config.ball_tail_particles));
+    true
+}
+
+// A diagonal direction picked from `seed`, which is usually a timestamp
+pub fn serve_direction(seed: i32) -> Vec2 {
+    Vec2::new((1 - 2 * (seed % 2).abs()) as f32, (1 - 2 * ((seed / 7) % 2).abs()) as f32)
+}
+
+// Bounces touching balls off each other as equal masses, returning whether
+// any collided
+pub fn collide_balls(balls: &mut [Ball], recorder: &mut Recorder) -> bool {
+    let mut collided = false;
+    for i in 1..balls.len() {
+        let (before, after) = balls.split_at_mut(i);
+        let b = &mut after[0];
+        for a in before.iter_mut() {
+            let contact = match collision::collide(&a.position, &a.shape, &b.position, &b.shape) {
+                Some(contact) => contact,
+                None => continue
+            };
+            let normal = contact.normal;
+            a.position.x += normal.x * contact.depth / 2.0;
+            a.position.y += normal.y * contact.depth / 2.0;
+            b.position.x -= normal.x * contact.depth / 2.0;
+            b.position.y -= normal.y * contact.depth / 2.0;
+
+            // Equal masses swap their velocities along the normal
+            let approach = (a.velocity.x - b.velocity.x) * normal.x + (a.velocity.y - b.velocity.y) * normal.y;
+            if approach >= 0.0 {
+                continue;
+            }
+            a.velocity.x -= approach * normal.x;
+            a.velocity.y -= approach * normal.y;
+            b.velocity.x += approach * normal.x;
+            b.velocity.y += approach * normal.y;
+
+            let radius = a.shape.extent().x;
+            recorder.impact(a.position.x - normal.x * radius, a.position.y - normal.y * radius,
+                            -approach * normal.x, -approach * normal.y);
+            collided = true;
+        }
+    }
+    collided
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(x: f32, dx: f32) -> Ball {
+        Ball::new(Vec2::new(x, 0.0), Vec2::new(dx, 0.5), 0)
+    }
+
+    #[test]
+    fn touching_balls_separate_and_swap_velocities() {
+        let mut recorder = Recorder::default();
+        let mut balls = vec![ball(-0.015, 1.0), ball(0.015, -0.5), ball(0.5, 0.0)];
+        let diameter = 2.0 * balls[0].shape.extent().x;
+        assert!(0.03 < diameter);
+
+        assert!(collide_balls(&mut balls, &mut recorder));
+        assert!(balls[1].position.x - balls[0].position.x >= diameter - 1e-6);
+        assert_eq!(balls[0].position.x + balls[1].position.x, 0.0);
+        assert_eq!((balls[0].velocity.x, balls[1].velocity.x), (-0.5, 1.0));
+        assert_eq!((balls[0].velocity.y, balls[1].velocity.y), (0.5, 0.5));
+        assert_eq!((balls[2].position.x, balls[2].velocity.x), (0.5, 0.0));
+
+        // Once apart and moving away from each other they don't collide again
+        assert!(!collide_balls(&mut balls, &mut recorder));
+    }
+
+    #[test]
+    fn overlapping_balls_moving_apart_are_only_separated() {
+        let mut recorder = Recorder::default();
+        let mut balls = vec![ball(-0.01, -1.0), ball(0.01, 1.0)];
+        assert!(!collide_balls(&mut balls, &mut recorder));
+        assert!(balls[1].position.x - balls[0].position.x > 0.02);
+        assert_eq!((balls[0].velocity.x, balls[1].velocity.x), (-1.0, 1.0));
+    }
+}
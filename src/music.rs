@@ -0,0 +1,197 @@
+use web_sys::{AudioBuffer, AudioContext};
+
+use crate::data;
+use crate::error::{describe, PongError};
+
+// Notes are scheduled this far ahead of the audio clock so that a slow
+// animation frame never leaves a gap in the music
+const SCHEDULE_AHEAD: f64 = 0.2;
+const START_DELAY: f64 = 0.05;
+
+const STEPS_PER_BEAT: f64 = 4.0;
+const BASE_TEMPO: f64 = 120.0;
+const TEMPO_PER_HIT: f64 = 3.0;
+const MAX_RALLY_TEMPO: f64 = 48.0;
+const MATCH_POINT_TEMPO: f64 = 12.0;
+
+pub struct Intensity {
+    pub rally: u32,
+    pub match_point: bool
+}
+
+// A channel's pattern and sound, and the intensity at which it joins in
+struct Layer {
+    notes: &'static [u8],
+    volume: f32,
+    gate: f32,
+    min_rally: u32,
+    match_point_only: bool
+}
+
+const LAYERS: [Layer; 3] = [
+    Layer { notes: &data::MUSIC_BASS, volume: 0.06, gate: 0.2, min_rally: 0, match_point_only: false },
+    Layer { notes: &data::MUSIC_ARPEGGIO, volume: 0.025, gate: 0.08, min_rally: 3, match_point_only: false },
+    Layer { notes: &data::MUSIC_LEAD, volume: 0.04, gate: 0.3, min_rally: 0, match_point_only: true }
+];
+
+struct Channel {
+    layer: &'static Layer,
+    buffers: Vec<Option<AudioBuffer>>
+}
+
+pub struct Sequencer {
+    channels: Vec<Channel>,
+    step: usize,
+    next_step_time: f64,
+    playing: bool
+}
+
+impl Intensity {
+    fn tempo(&self) -> f64 {
+        let rally = (self.rally as f64 * TEMPO_PER_HIT).min(MAX_RALLY_TEMPO);
+        let match_point = if self.match_point { MATCH_POINT_TEMPO } else { 0.0 };
+        BASE_TEMPO + rally + match_point
+    }
+    fn step_duration(&self) -> f64 {
+        60.0 / self.tempo() / STEPS_PER_BEAT
+    }
+}
+
+impl Layer {
+    fn active(&self, intensity: &Intensity) -> bool {
+        intensity.rally >= self.min_rally && (intensity.match_point || !self.match_point_only)
+    }
+}
+
+impl Channel {
+    fn new(ctx: &AudioContext, layer: &'static Layer) -> Result<Channel, PongError> {
+        let buffers = (0..128u8).map(|pitch| {
+            if layer.notes.contains(&pitch) && pitch != 0 {
+                render_note(ctx, pitch, layer.volume, layer.gate).map(Some)
+            } else {
+                Ok(None)
+            }
+        }).collect::<Result<_, _>>()?;
+
+        Ok(Channel { layer, buffers })
+    }
+}
+
+impl Sequencer {
+    pub fn new(ctx: &AudioContext) -> Result<Sequencer, PongError> {
+        let channels = LAYERS.iter()
+            .map(|layer| Channel::new(ctx, layer))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Sequencer {
+            channels,
+            step: 0,
+            next_step_time: 0.0,
+            playing: false
+        })
+    }
+    pub fn start(&mut self, ctx: &AudioContext) {
+        self.step = 0;
+        self.next_step_time = ctx.current_time() + START_DELAY;
+        self.playing = true;
+    }
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+    pub fn update(&mut self, ctx: &AudioContext, intensity: &Intensity) -> Result<(), PongError> {
+        if !self.playing {
+            return Ok(());
+        }
+
+        let now = ctx.current_time();
+
+        // Skip missed steps instead of playing them all at once, e.g. after
+        // the audio context has been suspended
+        if self.next_step_time < now {
+            self.next_step_time = now + START_DELAY;
+        }
+
+        let step_duration = intensity.step_duration();
+
+        while self.next_step_time < now + SCHEDULE_AHEAD {
+            for channel in self.channels.iter().filter(|c| c.layer.active(intensity)) {
+                let pitch = channel.layer.notes[self.step % channel.layer.notes.len()];
+                if let Some(buffer) = &channel.buffers[pitch as usize] {
+                    schedule_buffer(ctx, buffer, self.next_step_time)?;
+                }
+            }
+            self.step += 1;
+            self.next_step_time += step_duration;
+        }
+
+        Ok(())
+    }
+}
+
+fn render_note(ctx: &AudioContext, pitch: u8, volume: f32, gate: f32) -> Result<AudioBuffer, PongError> {
+    let sample_rate = ctx.sample_rate();
+    let frequency = 440.0 * 2f32.powf((pitch as f32 - 69.0) / 12.0);
+    let period = sample_rate / frequency;
+    let length = (gate * sample_rate) as usize;
+
+    let samples: Vec<f32> = (0..length).map(|i| {
+        let sq = if (i as f32 / period) % 1.0 < 0.5 { volume } else { -volume };
+        let envelope = 1.0 - i as f32 / length as f32;
+        sq * envelope
+    }).collect();
+
+    let buffer = ctx.create_buffer(1, length as u32, sample_rate)
+        .map_err(|e| PongError::Audio(format!("Unable to create note buffer: {}", describe(e))))?;
+    buffer.copy_to_channel(&samples, 0)
+        .map_err(|e| PongError::Audio(format!("Unable to fill note buffer: {}", describe(e))))?;
+    Ok(buffer)
+}
+
+fn schedule_buffer(ctx: &AudioContext, buffer: &AudioBuffer, time: f64) -> Result<(), PongError> {
+    let source = ctx.create_buffer_source()
+        .map_err(|e| PongError::Audio(format!("Unable to create note source: {}", describe(e))))?;
+    source.set_buffer(Some(buffer));
+    source.connect_with_audio_node(&ctx.destination())
+        .map_err(|e| PongError::Audio(format!("Unable to connect note source: {}", describe(e))))?;
+    source.start_with_when(time)
+        .map_err(|e| PongError::Audio(format!("Unable to schedule note: {}", describe(e))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(rally: u32, match_point: bool) -> Vec<&'static [u8]> {
+        let intensity = Intensity { rally, match_point };
+        LAYERS.iter().filter(|l| l.active(&intensity)).map(|l| l.notes).collect()
+    }
+
+    #[test]
+    fn tempo_rises_with_rally_length_up_to_a_cap() {
+        let tempo = |rally| Intensity { rally, match_point: false }.tempo();
+        assert_eq!(tempo(0), BASE_TEMPO);
+        assert_eq!(tempo(4), BASE_TEMPO + 4.0 * TEMPO_PER_HIT);
+        assert_eq!(tempo(100), BASE_TEMPO + MAX_RALLY_TEMPO);
+        assert!(tempo(5) > tempo(4));
+    }
+
+    #[test]
+    fn match_point_adds_tempo_on_top_of_the_rally() {
+        let intensity = Intensity { rally: 100, match_point: true };
+        assert_eq!(intensity.tempo(), BASE_TEMPO + MAX_RALLY_TEMPO + MATCH_POINT_TEMPO);
+        assert!(intensity.step_duration() < Intensity { rally: 100, match_point: false }.step_duration());
+        assert_eq!(Intensity { rally: 0, match_point: false }.step_duration(), 0.125);
+    }
+
+    #[test]
+    fn channels_join_in_with_rally_length_and_match_point() {
+        let bass: &[u8] = &data::MUSIC_BASS;
+        let arpeggio: &[u8] = &data::MUSIC_ARPEGGIO;
+        let lead: &[u8] = &data::MUSIC_LEAD;
+        assert_eq!(active(0, false), vec![bass]);
+        assert_eq!(active(2, false), vec![bass]);
+        assert_eq!(active(3, false), vec![bass, arpeggio]);
+        assert_eq!(active(0, true), vec![bass, lead]);
+        assert_eq!(active(3, true), vec![bass, arpeggio, lead]);
+    }
+}
@@ -0,0 +1,499 @@
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGlTexture;
+
+use crate::{data, multiball, play_audio, Ball, Pong, RenderContext, Side, Vec2, GL};
+use crate::collision::{self, Shape};
+use crate::command::Commands;
+use crate::config::{from_js, GameConfig};
+use crate::error::PongError;
+use crate::events::GameEvent;
+use crate::gl::VertexArray;
+use crate::text::TextRenderer;
+
+const MAX_PICKUPS: usize = 2;
+const PICKUP_SHAPE: Shape = Shape::Circle { radius: 0.04 };
+// Pickups appear within this distance of the center
+const SPAWN_WIDTH: f32 = 0.5;
+const SPAWN_HEIGHT: f32 = 0.7;
+// Shields stand this far behind the paddles
+const SHIELD_DISTANCE: f32 = 0.07;
+const MAX_PADDLE_SCALE: f32 = 4.0;
+
+const TEXT_SIZE: f32 = 0.035;
+const LINE_HEIGHT: f32 = 0.05;
+const INDICATOR_BOTTOM: f32 = -0.9;
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Collector,
+    Opponent
+}
+
+// A power-up described as data: the game only reads the combined modifiers of
+// the active effects, so new power-ups need no code
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Effect {
+    pub name: String,
+    // Milliseconds the effect lasts, zero for instant effects
+    pub duration: f32,
+    pub target: Target,
+    pub color: [u8; 3],
+    pub paddle_scale: f32,
+    pub ball_speed: f32,
+    // Sideways acceleration of balls hit by the affected player
+    pub curve: f32,
+    pub shield: bool,
+    pub reverse_controls: bool,
+    pub split_ball: bool
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Modifiers {
+    pub paddle_scale: f32,
+    pub ball_speed: f32,
+    pub curve: f32,
+    pub shield: bool,
+    pub reverse_controls: bool
+}
+
+struct Active {
+    effect: usize,
+    side: Side,
+    remaining: f32
+}
+
+struct Pickup {
+    position: Vec2,
+    effect: usize
+}
+
+// Effect registry and the pickups and effects currently in play
+pub struct PowerUps {
+    effects: Vec<Effect>,
+    pickups: Vec<Pickup>,
+    active: Vec<Active>,
+    timer: f32,
+    spawned: u32,
+    graphics: Graphics
+}
+
+struct Graphics {
+    text: TextRenderer,
+    pickup: VertexArray,
+    shield: VertexArray,
+    textures: Vec<WebGlTexture>
+}
+
+impl Default for Effect {
+    fn default() -> Effect {
+        Effect {
+            name: String::new(),
+            duration: 0.0,
+            target: Target::Collector,
+            color: [0xFF, 0xFF, 0xFF],
+            paddle_scale: 1.0,
+            ball_speed: 1.0,
+            curve: 0.0,
+            shield: false,
+            reverse_controls: false,
+            split_ball: false
+        }
+    }
+}
+
+impl Effect {
+    pub fn from_js(value: JsValue) -> Result<Effect, PongError> {
+        let effect: Effect = from_js(value, "power-up")?;
+        effect.validate()?;
+        Ok(effect)
+    }
+
+    fn validate(&self) -> Result<(), PongError> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push(String::from("name must not be empty"));
+        }
+        if !(self.duration.is_finite() && self.duration >= 0.0) {
+            errors.push(format!("duration must be zero or positive, got {}", self.duration));
+        }
+        if !(self.paddle_scale > 0.0 && self.paddle_scale <= MAX_PADDLE_SCALE) {
+            errors.push(format!("paddle_scale must be above 0 and at most {}, got {}",
+                                MAX_PADDLE_SCALE, self.paddle_scale));
+        }
+        if !(self.ball_speed.is_finite() && self.ball_speed > 0.0) {
+            errors.push(format!("ball_speed must be a positive number, got {}", self.ball_speed));
+        }
+        if !self.curve.is_finite() {
+            errors.push(format!("curve must be a number, got {}", self.curve));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(PongError::Config(format!("Invalid power-up {}: {}", self.name, errors.join("; "))))
+        }
+    }
+}
+
+impl Default for Modifiers {
+    fn default() -> Modifiers {
+        Modifiers { paddle_scale: 1.0, ball_speed: 1.0, curve: 0.0, shield: false, reverse_controls: false }
+    }
+}
+
+impl Modifiers {
+    fn add(&mut self, effect: &Effect) {
+        self.paddle_scale = (self.paddle_scale * effect.paddle_scale).min(MAX_PADDLE_SCALE);
+        self.ball_speed *= effect.ball_speed;
+        self.curve += effect.curve;
+        self.shield |= effect.shield;
+        self.reverse_controls |= effect.reverse_controls;
+    }
+}
+
+impl PowerUps {
+    pub fn new(ctx: &RenderContext) -> Result<PowerUps, PongError> {
+        let effects = builtin_effects()?;
+        let graphics = Graphics::new(ctx, &effects)?;
+        Ok(PowerUps {
+            effects,
+            pickups: Vec::new(),
+            active: Vec::new(),
+            timer: 0.0,
+            spawned: 0,
+            graphics
+        })
+    }
+
+    pub fn reload(&mut self, ctx: &RenderContext) -> Result<(), PongError> {
+        self.graphics = Graphics::new(ctx, &self.effects)?;
+        Ok(())
+    }
+
+    // Adds an effect to the registry, replacing any effect with the same name
+    pub fn register(&mut self, ctx: &RenderContext, effect: Effect) -> Result<(), PongError> {
+        let texture = pickup_texture(ctx, &effect)?;
+        match self.effects.iter().position(|e| e.name == effect.name) {
+            Some(index) => {
+                self.effects[index] = effect;
+                self.graphics.textures[index] = texture;
+            },
+            None => {
+                self.effects.push(effect);
+                self.graphics.textures.push(texture);
+            }
+        }
+        Ok(())
+    }
+
+    // Combined modifiers of the effects active for `side`
+    pub fn modifiers(&self, side: Side) -> Modifiers {
+        combine(&self.effects, self.active.iter().filter(|a| a.side == side).map(|a| a.effect))
+    }
+
+    // Balls move at the combined speed of every active effect, whoever has it
+    pub fn ball_speed(&self) -> f32 {
+        combine(&self.effects, self.active.iter().map(|a| a.effect)).ball_speed
+    }
+
+    pub fn clear(&mut self) {
+        self.pickups.clear();
+        self.active.clear();
+        self.timer = 0.0;
+    }
+
+    pub fn render(&self, ctx: &RenderContext, config: &GameConfig) {
+        let graphics = &self.graphics;
+        graphics.pickup.bind(ctx);
+        ctx.gl.active_texture(GL::TEXTURE0);
+        ctx.gl.uniform1i(Some(&ctx.sampler), 0);
+        ctx.gl.uniform1f(Some(&ctx.opacity), 1.0);
+        for pickup in &self.pickups {
+            ctx.gl.bind_texture(GL::TEXTURE_2D, Some(&graphics.textures[pickup.effect]));
+            ctx.gl.uniform4f(Some(&ctx.offset), pickup.position.x, pickup.position.y, 0.0, 0.0);
+            ctx.draw(data::PICKUP_VERTICES.len() as i32 / 4);
+        }
+
+        graphics.shield.bind(ctx);
+        ctx.gl.uniform2f(Some(&ctx.size), 1.0, config.wall);
+        for active in &self.active {
+            let effect = &self.effects[active.effect];
+            if !effect.shield {
+                continue;
+            }
+            let x = match active.side {
+                Side::Left => -shield_x(config),
+                Side::Right => shield_x(config),
+                Side::Top | Side::Bottom => continue
+            };
+            ctx.gl.bind_texture(GL::TEXTURE_2D, Some(&graphics.textures[active.effect]));
+            ctx.gl.uniform4f(Some(&ctx.offset), x, 0.0, 0.0, 0.0);
+            ctx.draw(data::SHIELD_VERTICES.len() as i32 / 4);
+        }
+        ctx.gl.uniform2f(Some(&ctx.size), 1.0, 1.0);
+
+        // Active effects are listed above each player's corner with the
+        // seconds they have left
+        for side in [Side::Left, Side::Right] {
+            let active = self.active.iter().filter(|a| a.side == side);
+            for (i, active) in active.enumerate() {
+                let line = format!("{} {}", self.effects[active.effect].name.to_uppercase(),
+                                   (active.remaining / 1000.0).ceil());
+                let x = if side == Side::Left { -0.95 } else { 0.95 - TextRenderer::width(&line, TEXT_SIZE) };
+                let y = INDICATOR_BOTTOM + (i + 1) as f32 * LINE_HEIGHT;
+                graphics.text.render(ctx, &line, x, y, TEXT_SIZE, 0.8);
+            }
+        }
+    }
+}
+
+impl Graphics {
+    fn new(ctx: &RenderContext, effects: &[Effect]) -> Result<Graphics, PongError> {
+        let pickup = VertexArray::new(ctx)?;
+        pickup.upload(ctx, &data::PICKUP_VERTICES, GL::STATIC_DRAW);
+        let shield = VertexArray::new(ctx)?;
+        shield.upload(ctx, &data::SHIELD_VERTICES, GL::STATIC_DRAW);
+        Ok(Graphics {
+            text: TextRenderer::new(ctx)?,
+            pickup, shield,
+            textures: effects.iter().map(|effect| pickup_texture(ctx, effect)).collect::<Result<_, _>>()?
+        })
+    }
+}
+
+// A round token in the effect's color with a lighter center
+fn pickup_texture(ctx: &RenderContext, effect: &Effect) -> Result<WebGlTexture, PongError> {
+    let [r, g, b] = effect.color;
+    let light = |c: u8| c / 2 + 0x80;
+    let mut pixels = Vec::with_capacity(4 * 4 * 4);
+    for y in 0..4 {
+        for x in 0..4 {
+            let corner = (x == 0 || x == 3) && (y == 0 || y == 3);
+            let center = (1..3).contains(&x) && (1..3).contains(&y);
+            pixels.extend_from_slice(&match (corner, center) {
+                (true, _) => [0, 0, 0, 0],
+                (_, true) => [light(r), light(g), light(b), 0xFF],
+                _ => [r, g, b, 0xFF]
+            });
+        }
+    }
+    ctx.load_texture(&pixels, 4, 4)
+}
+
+fn builtin_effects() -> Result<Vec<Effect>, PongError> {
+    let effects: Vec<Effect> = serde_json::from_str(data::POWERUPS)
+        .map_err(|e| PongError::Config(format!("Invalid built-in power-ups: {}", e)))?;
+    for effect in &effects {
+        effect.validate()?;
+    }
+    Ok(effects)
+}
+
+fn combine(effects: &[Effect], active: impl Iterator<Item = usize>) -> Modifiers {
+    let mut modifiers = Modifiers::default();
+    for effect in active {
+        modifiers.add(&effects[effect]);
+    }
+    modifiers
+}
+
+pub fn shield_x(config: &GameConfig) -> f32 {
+    config.paddle_x + SHIELD_DISTANCE
+}
+
+// Counts down active effects, spawns pickups, hands out pickups touched by
+// balls and bounces balls off shields
+pub fn update(pong: &mut Pong, delta: f32, seed: i32) {
+    let config = &pong.config;
+    let powerups = &mut pong.powerups;
+    for active in &mut powerups.active {
+        active.remaining -= delta;
+    }
+    powerups.active.retain(|active| active.remaining > 0.0);
+
+    if config.powerups && !powerups.effects.is_empty() {
+        powerups.timer += delta;
+        if powerups.timer >= config.powerup_interval {
+            powerups.timer = 0.0;
+            if powerups.pickups.len() < MAX_PICKUPS {
+                powerups.spawned += 1;
+                let random = |salt: u32| hash(seed as u32 ^ powerups.spawned.wrapping_mul(salt));
+                let unit = |salt: u32| random(salt) as f32 / u32::MAX as f32 * 2.0 - 1.0;
+                let position = Vec2::new(unit(0x9E37_79B9) * SPAWN_WIDTH, unit(0x85EB_CA6B) * SPAWN_HEIGHT);
+                let effect = random(0xC2B2_AE35) as usize % powerups.effects.len();
+                powerups.pickups.push(Pickup { position, effect });
+            }
+        }
+    }
+
+    for (effect, side, ball) in collect(&mut powerups.pickups, &pong.balls) {
+        activate(pong, effect, side, Some(ball));
+    }
+
+    let shield_x = shield_x(&pong.config);
+    let powerups = &pong.powerups;
+    for ball in pong.balls.iter_mut() {
+        if !shield_bounce(ball, shield_x, |side| powerups.modifiers(side).shield) {
+            continue;
+        }
+        play_audio(&pong.audio, &pong.boop);
+        let radius = ball.shape.extent().x;
+        pong.recorder.impact(ball.position.x + ball.position.x.signum() * radius, ball.position.y,
+                             2.0 * ball.velocity.x, 0.0);
+    }
+}
+
+// Takes the pickups touched by balls, returning each one's effect, the player
+// who last hit the ball that collected it and that ball's index
+fn collect(pickups: &mut Vec<Pickup>, balls: &[Ball]) -> Vec<(usize, Side, usize)> {
+    let mut collected = Vec::new();
+    for (index, ball) in balls.iter().enumerate() {
+        let side = match ball.last_hitter {
+            Some(side) => side,
+            None => continue
+        };
+        pickups.retain(|pickup| {
+            let touched = collision::collide(&ball.position, &ball.shape, &pickup.position, &PICKUP_SHAPE).is_some();
+            if touched {
+                collected.push((pickup.effect, side, index));
+            }
+            !touched
+        });
+    }
+    collected
+}
+
+// Bounces a ball heading out past the shield of a `shielded` side back in,
+// returning whether it did
+fn shield_bounce(ball: &mut Ball, shield_x: f32, shielded: impl Fn(Side) -> bool) -> bool {
+    let outward = ball.velocity.x * ball.position.x > 0.0;
+    let radius = ball.shape.extent().x;
+    if !outward || ball.position.x.abs() + radius < shield_x || !shielded(Side::of(ball.position.x)) {
+        return false;
+    }
+    ball.position.x = ball.position.x.signum() * (shield_x - radius);
+    ball.velocity.x = -ball.velocity.x;
+    true
+}
+
+// Applies an effect collected by `collector`, optionally with the ball that
+// collected it
+fn activate(pong: &mut Pong, effect: usize, collector: Side, ball: Option<usize>) {
+    let powerups = &mut pong.powerups;
+    let e = &powerups.effects[effect];
+    let side = match e.target {
+        Target::Collector => collector,
+        Target::Opponent => collector.opponent()
+    };
+    pong.events.push(GameEvent::PowerUp { effect: e.name.clone(), side });
+
+    // Collecting an effect again restarts it
+    let duration = e.duration;
+    let split = e.split_ball;
+    if duration > 0.0 {
+        match powerups.active.iter_mut().find(|a| a.effect == effect && a.side == side) {
+            Some(active) => active.remaining = duration,
+            None => powerups.active.push(Active { effect, side, remaining: duration })
+        }
+    }
+
+    let source = match ball.and_then(|index| pong.balls.get(index)) {
+        Some(source) if split => source,
+        _ => return
+    };
+    // The new ball leaves mirrored across the collecting ball's direction
+    let position = source.position.clone();
+    let velocity = Vec2::new(source.velocity.x, -source.velocity.y);
+    let last_hitter = source.last_hitter;
+    if multiball::add(pong, position, velocity) {
+        if let Some(ball) = pong.balls.last_mut() {
+            ball.last_hitter = last_hitter;
+        }
+    }
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^ (x >> 16)
+}
+
+pub fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("powerup", "powerup <name> <left|right>", |pong, args| {
+        let effect = pong.powerups.effects.iter().position(|e| e.name == args[0])
+            .ok_or_else(|| format!("Unknown power-up {}", args[0]))?;
+        let side = match args[1] {
+            "left" => Side::Left,
+            "right" => Side::Right,
+            side => return Err(format!("Unknown side {}", side))
+        };
+        let ball = if pong.balls.is_empty() { None } else { Some(0) };
+        activate(pong, effect, side, ball);
+        Ok(format!("{} for {}", args[0], args[1]))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pickups_go_to_the_last_hitter() {
+        let mut pickups = vec![
+            Pickup { position: Vec2::new(0.0, 0.0), effect: 0 },
+            Pickup { position: Vec2::new(0.3, 0.3), effect: 1 },
+            Pickup { position: Vec2::new(-0.3, -0.3), effect: 2 }
+        ];
+        let mut served = Ball::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0), 0);
+        served.last_hitter = None;
+        let mut returned = Ball::new(Vec2::new(0.32, 0.3), Vec2::new(1.0, 1.0), 0);
+        returned.last_hitter = Some(Side::Right);
+
+        // Balls nobody has hit yet leave pickups alone
+        let collected = collect(&mut pickups, &[served, returned]);
+        assert!(collected == vec![(1, Side::Right, 1)]);
+        assert_eq!(pickups.len(), 2);
+        assert!(pickups.iter().all(|pickup| pickup.effect != 1));
+    }
+
+    #[test]
+    fn shields_bounce_balls_back_on_their_side_only() {
+        let config = GameConfig::default();
+        let x = shield_x(&config);
+        let shielded = |side| side == Side::Left;
+
+        let mut ball = Ball::new(Vec2::new(-x, 0.2), Vec2::new(-1.0, 0.5), 0);
+        assert!(shield_bounce(&mut ball, x, shielded));
+        assert_eq!(ball.velocity.x, 1.0);
+        assert_eq!(ball.velocity.y, 0.5);
+        assert!((ball.position.x - ball.shape.extent().x + x).abs() < 1e-6);
+        // Once turned around the ball is let through
+        assert!(!shield_bounce(&mut ball, x, shielded));
+
+        let mut ball = Ball::new(Vec2::new(x, 0.2), Vec2::new(1.0, 0.5), 0);
+        assert!(!shield_bounce(&mut ball, x, shielded));
+        assert_eq!(ball.velocity.x, 1.0);
+    }
+
+    #[test]
+    fn combines_active_effects() {
+        let effects = builtin_effects().unwrap();
+        let index = |name: &str| effects.iter().position(|e| e.name == name).unwrap();
+
+        assert_eq!(combine(&effects, std::iter::empty()), Modifiers::default());
+        let modifiers = combine(&effects, [index("big_paddle"), index("shield"), index("fast_ball")].iter().copied());
+        assert_eq!(modifiers.paddle_scale, effects[index("big_paddle")].paddle_scale);
+        assert_eq!(modifiers.ball_speed, effects[index("fast_ball")].ball_speed);
+        assert!(modifiers.shield);
+        assert!(!modifiers.reverse_controls);
+
+        let both = combine(&effects, [index("big_paddle"), index("small_paddle")].iter().copied());
+        let scale = effects[index("big_paddle")].paddle_scale * effects[index("small_paddle")].paddle_scale;
+        assert!((both.paddle_scale - scale).abs() < 1e-6);
+    }
+}
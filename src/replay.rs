@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+
+use crate::{create_sparks, Ball, ParticleSystem, Pong, Side, Vec2};
+use crate::command::Commands;
+use crate::config::GameConfig;
+
+// Simulation time kept for replays, in milliseconds
+const RECORD_LENGTH: f32 = 3000.0;
+const REPLAY_SPEED: f32 = 0.4;
+
+// Simulation state at the end of a frame, with the spark bursts created
+// during it
+#[derive(Clone)]
+struct Snapshot {
+    delta: f32,
+    balls: Vec<Vec2>,
+    // Every paddle in play on the left and right
+    paddles: Vec<(Side, Vec2)>,
+    impacts: Vec<Impact>
+}
+
+#[derive(Clone)]
+struct Impact {
+    position: Vec2,
+    direction: Vec2
+}
+
+// Keeps the last few seconds of the point being played
+#[derive(Default)]
+pub struct Recorder {
+    frames: VecDeque<Snapshot>,
+    impacts: Vec<Impact>,
+    last_point: Vec<Snapshot>,
+    saved: Vec<Snapshot>
+}
+
+// Plays back recorded frames, regenerating the particle effects
+pub struct Replay {
+    frames: Vec<Snapshot>,
+    next: usize,
+    time: f32,
+    pub balls: Vec<Vec2>,
+    pub paddles: Vec<(Side, Vec2)>,
+    pub ball_tail: ParticleSystem,
+    pub sparks: ParticleSystem,
+    sparks_per_hit: usize
+}
+
+impl Recorder {
+    // Adds a spark burst to the current frame
+    pub fn impact(&mut self, x: f32, y: f32, dx: f32, dy: f32) {
+        self.impacts.push(Impact { position: Vec2::new(x, y), direction: Vec2::new(dx, dy) });
+    }
+
+    pub fn create_sparks(&self, sparks: &mut ParticleSystem, count: usize) {
+        create_impact_sparks(&self.impacts, sparks, count);
+    }
+
+    pub fn record(&mut self, delta: f32, balls: &[Ball], paddles: Vec<(Side, Vec2)>) {
+        self.frames.push_back(Snapshot {
+            delta,
+            balls: balls.iter().map(|ball| ball.position.clone()).collect(),
+            paddles,
+            impacts: std::mem::take(&mut self.impacts)
+        });
+        let mut length: f32 = self.frames.iter().map(|frame| frame.delta).sum();
+        while length > RECORD_LENGTH {
+            match self.frames.pop_front() {
+                Some(frame) => length -= frame.delta,
+                None => break
+            }
+        }
+    }
+
+    // Ends the recording of a point, returning its replay
+    pub fn finish_point(&mut self, config: &GameConfig) -> Replay {
+        self.last_point = self.frames.drain(..).collect();
+        Replay::new(self.last_point.clone(), config)
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.impacts.clear();
+    }
+}
+
+impl Replay {
+    fn new(frames: Vec<Snapshot>, config: &GameConfig) -> Replay {
+        let (balls, paddles) = match frames.first() {
+            Some(frame) => (frame.balls.clone(), frame.paddles.clone()),
+            None => (Vec::new(), Vec::new())
+        };
+        Replay {
+            balls, paddles,
+            frames,
+            next: 0,
+            time: 0.0,
+            // One tail is shared by all the balls in the recording
+            ball_tail: ParticleSystem::new(config.ball_tail_particles * config.max_balls),
+            sparks: ParticleSystem::new(config.spark_particles),
+            sparks_per_hit: config.sparks_per_hit
+        }
+    }
+
+    // Plays back `delta` milliseconds of wall clock time in slow motion.
+    // Returns false once the replay has ended.
+    pub fn advance(&mut self, delta: f32) -> bool {
+        self.time += delta * REPLAY_SPEED;
+        while let Some(frame) = self.frames.get(self.next) {
+            if frame.delta > self.time {
+                break;
+            }
+            self.time -= frame.delta;
+            self.next += 1;
+
+            self.balls = frame.balls.clone();
+            self.paddles = frame.paddles.clone();
+            create_impact_sparks(&frame.impacts, &mut self.sparks, self.sparks_per_hit);
+            for ball in &frame.balls {
+                self.ball_tail.add(ball.clone(), Vec2::zero(), Vec2::zero(), 1000.0);
+            }
+            self.ball_tail.update(frame.delta);
+            self.sparks.update(frame.delta);
+        }
+        self.next < self.frames.len()
+    }
+}
+
+fn create_impact_sparks(impacts: &[Impact], sparks: &mut ParticleSystem, count: usize) {
+    for impact in impacts {
+        create_sparks(sparks, count, impact.position.x, impact.position.y,
+                      impact.direction.x, impact.direction.y);
+    }
+}
+
+pub fn register_commands(commands: &mut Commands<Pong>) {
+    commands.register("replay", "replay <play|save>", |pong, args| {
+        let recorder = &mut pong.recorder;
+        match args[0] {
+            "save" if recorder.last_point.is_empty() => Err(String::from("No point to save yet")),
+            "save" => {
+                recorder.saved = recorder.last_point.clone();
+                Ok(String::from("Saved the last point"))
+            },
+            "play" => {
+                let frames = if recorder.saved.is_empty() { &recorder.last_point } else { &recorder.saved };
+                if frames.is_empty() {
+                    return Err(String::from("Nothing to replay yet"));
+                }
+                pong.replay = Some(Replay::new(frames.clone(), &pong.config));
+                Ok(String::from("Replaying, press any key to skip"))
+            },
+            action => Err(format!("Unknown replay action {}", action))
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f32 = 20.0;
+
+    // Records `count` frames with the ball at x = frame number
+    fn record(recorder: &mut Recorder, count: usize) {
+        for i in 0..count {
+            let ball = Ball::new(Vec2::new(i as f32, 0.0), Vec2::zero(), 0);
+            recorder.record(FRAME, &[ball], vec![(Side::Left, Vec2::new(-0.9, 0.0)), (Side::Right, Vec2::new(0.9, i as f32))]);
+        }
+    }
+
+    #[test]
+    fn recorder_keeps_the_last_record_length() {
+        let mut recorder = Recorder::default();
+        record(&mut recorder, 500);
+        let kept = (RECORD_LENGTH / FRAME) as usize;
+        assert_eq!(recorder.frames.len(), kept);
+        assert_eq!(recorder.frames[0].balls[0].x, (500 - kept) as f32);
+        assert_eq!(recorder.frames[kept - 1].balls[0].x, 499.0);
+    }
+
+    #[test]
+    fn finished_points_keep_their_frames_and_impacts() {
+        let config = GameConfig::default();
+        let mut recorder = Recorder::default();
+        record(&mut recorder, 3);
+        recorder.impact(0.5, 0.5, 1.0, 0.0);
+        record(&mut recorder, 1);
+
+        let replay = recorder.finish_point(&config);
+        assert!(recorder.frames.is_empty());
+        assert_eq!(recorder.last_point.len(), 4);
+        assert_eq!(recorder.last_point[3].impacts.len(), 1);
+        assert!(recorder.last_point[..3].iter().all(|frame| frame.impacts.is_empty()));
+        assert_eq!(replay.frames.len(), 4);
+        assert_eq!(replay.balls[0].x, 0.0);
+
+        // The next point starts a new recording
+        record(&mut recorder, 2);
+        assert_eq!(recorder.frames.len(), 2);
+        assert_eq!(recorder.last_point.len(), 4);
+    }
+
+    #[test]
+    fn replay_moves_through_frames_in_slow_motion() {
+        let config = GameConfig::default();
+        let mut recorder = Recorder::default();
+        record(&mut recorder, 3);
+        let mut replay = recorder.finish_point(&config);
+
+        // Frames are shown once their whole duration has been played back
+        assert!(replay.advance(FRAME));
+        assert_eq!(replay.next, 0);
+        assert!(replay.advance(FRAME / REPLAY_SPEED - FRAME));
+        assert_eq!(replay.next, 1);
+        assert_eq!(replay.balls[0].x, 0.0);
+
+        assert!(replay.advance(FRAME / REPLAY_SPEED));
+        assert_eq!(replay.next, 2);
+        assert_eq!(replay.balls[0].x, 1.0);
+        assert_eq!(replay.paddles[1].1.y, 1.0);
+
+        // Several frames can pass in one long wall clock frame
+        assert!(!replay.advance(2.0 * FRAME / REPLAY_SPEED));
+        assert_eq!(replay.balls[0].x, 2.0);
+    }
+
+    #[test]
+    fn replays_show_every_recorded_paddle() {
+        let config = GameConfig::default();
+        let mut recorder = Recorder::default();
+        let ball = Ball::new(Vec2::zero(), Vec2::zero(), 0);
+        let paddles = vec![
+            (Side::Left, Vec2::new(-0.9, 0.1)),
+            (Side::Right, Vec2::new(0.9, 0.2)),
+            (Side::Left, Vec2::new(-0.5, 0.3)),
+            (Side::Right, Vec2::new(0.5, 0.4)),
+        ];
+        recorder.record(FRAME, &[ball], paddles.clone());
+        let replay = recorder.finish_point(&config);
+        assert_eq!(replay.paddles.len(), 4);
+        assert!(replay.paddles.iter().zip(&paddles).all(|(shown, recorded)| {
+            shown.0 == recorded.0 && shown.1.x == recorded.1.x && shown.1.y == recorded.1.y
+        }));
+    }
+}
@@ -0,0 +1,260 @@
+use web_sys::{WebGlProgram, WebGlShader};
+
+use crate::GL;
+use crate::error::PongError;
+use crate::gl::Gl;
+
+#[derive(Clone, Copy)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment
+}
+
+impl ShaderStage {
+    fn gl_type(self) -> u32 {
+        match self {
+            ShaderStage::Vertex => GL::VERTEX_SHADER,
+            ShaderStage::Fragment => GL::FRAGMENT_SHADER
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::Fragment => "fragment"
+        }
+    }
+}
+
+pub fn compile_shader(ctx: &Gl, stage: ShaderStage, source: &str) -> Result<WebGlShader, PongError> {
+    let shader = ctx.create_shader(stage.gl_type())
+        .ok_or_else(|| PongError::Shader(format!("Unable to create {} shader object", stage.name())))?;
+    ctx.shader_source(&shader, source);
+    ctx.compile_shader(&shader);
+
+    let ok = ctx.get_shader_parameter(&shader, GL::COMPILE_STATUS)
+            .as_bool()
+            .unwrap_or(false);
+    let log = ctx.get_shader_info_log(&shader).unwrap_or_default();
+    if ok {
+        if !log.trim().is_empty() {
+            gloo::console::warn!(format!("{} shader compiled with warnings:\n{}",
+                                         stage.name(), annotate(source, &log)));
+        }
+        Ok(shader)
+    } else if log.trim().is_empty() {
+        Err(PongError::Shader(format!("Unknown error compiling {} shader", stage.name())))
+    } else {
+        Err(PongError::Shader(format!("Unable to compile {} shader:\n{}",
+                                      stage.name(), annotate(source, &log))))
+    }
+}
+
+pub fn link_program(ctx: &Gl, vert_shader: &WebGlShader, frag_shader: &WebGlShader) -> Result<WebGlProgram, PongError> {
+    let program = ctx.create_program()
+        .ok_or_else(|| PongError::Shader(String::from("Unable to create program object")))?;
+
+    ctx.attach_shader(&program, vert_shader);
+    ctx.attach_shader(&program, frag_shader);
+    ctx.link_program(&program);
+
+    let ok = ctx.get_program_parameter(&program, GL::LINK_STATUS)
+        .as_bool()
+        .unwrap_or(false);
+    let log = ctx.get_program_info_log(&program).unwrap_or_default();
+    if ok {
+        if !log.trim().is_empty() {
+            gloo::console::warn!(format!("Shader program linked with warnings:\n{}", log.trim()));
+        }
+        Ok(program)
+    } else if log.trim().is_empty() {
+        Err(PongError::Shader(String::from("Unknown error linking shader program")))
+    } else {
+        Err(PongError::Shader(format!("Unable to link shader program:\n{}", log.trim())))
+    }
+}
+
+// Formats an info log followed by the shader source with line numbers, marking
+// the lines the log refers to
+fn annotate(source: &str, log: &str) -> String {
+    let lines = error_lines(log);
+    let mut listing = format!("{}\n", log.trim());
+    for (i, line) in source.lines().enumerate() {
+        let marker = if lines.contains(&(i + 1)) { ">>" } else { "  " };
+        listing.push_str(&format!("\n{} {:3} | {}", marker, i + 1, line));
+    }
+    listing
+}
+
+// Finds the line numbers referred to by an info log, which drivers format as
+// e.g. "ERROR: 0:12: ..." (ANGLE) or "0:12(5): error: ..." (Mesa)
+fn error_lines(log: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    for entry in log.lines() {
+        let bytes = entry.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let at_number = bytes[i].is_ascii_digit() && (i == 0 || !bytes[i - 1].is_ascii_alphanumeric());
+            if !at_number {
+                i += 1;
+                continue;
+            }
+            let file_end = i + bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+            if bytes.get(file_end) == Some(&b':') {
+                let line_end = file_end + 1 + bytes[file_end + 1..].iter()
+                    .take_while(|b| b.is_ascii_digit()).count();
+                if let Ok(line) = entry[file_end + 1..line_end].parse() {
+                    lines.push(line);
+                    break;
+                }
+            }
+            i = file_end;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use glsl::parser::Parse;
+    use glsl::syntax::{
+        Block, Expr, FunctionParameterDeclarator, SingleDeclaration, SingleDeclarationNoType,
+        StorageQualifier, TranslationUnit, TypeQualifier, TypeQualifierSpec
+    };
+    use glsl::visitor::{Host, Visit, Visitor};
+
+    use super::error_lines;
+    use crate::data;
+
+    const BUILTINS: [&str; 2] = ["gl_Position", "gl_FragColor"];
+    const UNIFORMS: [&str; 6] = ["u_offset", "u_size", "u_rotation", "u_scale", "u_sampler", "u_opacity"];
+
+    #[derive(Default)]
+    struct Declarations {
+        declared: HashSet<String>,
+        used: HashSet<String>,
+        qualified: HashMap<String, (StorageQualifier, String)>,
+        blocks: HashMap<String, StorageQualifier>
+    }
+
+    fn storage(qualifier: &Option<TypeQualifier>) -> Option<StorageQualifier> {
+        qualifier.iter()
+            .flat_map(|q| q.qualifiers.0.iter())
+            .find_map(|q| match q {
+                TypeQualifierSpec::Storage(storage) => Some(storage.clone()),
+                _ => None
+            })
+    }
+
+    impl Visitor for Declarations {
+        fn visit_single_declaration(&mut self, declaration: &SingleDeclaration) -> Visit {
+            if let Some(name) = &declaration.name {
+                self.declared.insert(name.0.clone());
+                if let Some(storage) = storage(&declaration.ty.qualifier) {
+                    let ty = format!("{:?}", declaration.ty.ty.ty);
+                    self.qualified.insert(name.0.clone(), (storage, ty));
+                }
+            }
+            Visit::Children
+        }
+        // Members of an unnamed block are used like plain variables
+        fn visit_block(&mut self, block: &Block) -> Visit {
+            let storage = storage(&Some(block.qualifier.clone()));
+            if let Some(storage) = &storage {
+                self.blocks.insert(block.name.0.clone(), storage.clone());
+            }
+            for field in &block.fields {
+                for name in field.identifiers.0.iter() {
+                    self.declared.insert(name.ident.0.clone());
+                    if let Some(storage) = &storage {
+                        self.qualified.insert(name.ident.0.clone(),
+                                              (storage.clone(), format!("{:?}", field.ty.ty)));
+                    }
+                }
+            }
+            Visit::Children
+        }
+        fn visit_single_declaration_no_type(&mut self, declaration: &SingleDeclarationNoType) -> Visit {
+            self.declared.insert(declaration.ident.ident.0.clone());
+            Visit::Children
+        }
+        fn visit_function_parameter_declarator(&mut self, parameter: &FunctionParameterDeclarator) -> Visit {
+            self.declared.insert(parameter.ident.ident.0.clone());
+            Visit::Children
+        }
+        fn visit_expr(&mut self, expr: &Expr) -> Visit {
+            if let Expr::Variable(name) = expr {
+                self.used.insert(name.0.clone());
+            }
+            Visit::Children
+        }
+    }
+
+    impl Declarations {
+        fn with(&self, storage: StorageQualifier) -> HashMap<&str, &str> {
+            self.qualified.iter()
+                .filter(|(_, (s, _))| *s == storage)
+                .map(|(name, (_, ty))| (name.as_str(), ty.as_str()))
+                .collect()
+        }
+    }
+
+    // Only checks that the source parses and every identifier it uses is declared;
+    // type checking and driver limits are left to the GL compiler at startup
+    fn parse_declarations(source: &str) -> Declarations {
+        let unit = TranslationUnit::parse(source)
+            .unwrap_or_else(|e| panic!("Shader does not parse:\n{}", e));
+        let mut declarations = Declarations::default();
+        unit.visit(&mut declarations);
+
+        for name in &declarations.used {
+            assert!(declarations.declared.contains(name) || BUILTINS.contains(&name.as_str()),
+                    "Undeclared identifier {}", name);
+        }
+        declarations
+    }
+
+    #[test]
+    fn shaders_parse_and_declare_render_context_names() {
+        let vertex = parse_declarations(data::VERTEX_SHADER);
+        let fragment = parse_declarations(data::FRAGMENT_SHADER);
+
+        let attributes = vertex.with(StorageQualifier::Attribute);
+        assert!(attributes.contains_key("a_position"));
+        assert!(attributes.contains_key("a_texcoord"));
+
+        let mut uniforms = vertex.with(StorageQualifier::Uniform);
+        uniforms.extend(fragment.with(StorageQualifier::Uniform));
+        for name in UNIFORMS {
+            assert!(uniforms.contains_key(name), "Missing uniform {}", name);
+        }
+
+        assert_eq!(vertex.with(StorageQualifier::Varying), fragment.with(StorageQualifier::Varying));
+    }
+
+    #[test]
+    fn webgl2_shaders_parse_and_declare_render_context_names() {
+        let vertex = parse_declarations(data::VERTEX_SHADER_300);
+        let fragment = parse_declarations(data::FRAGMENT_SHADER_300);
+
+        let inputs = vertex.with(StorageQualifier::In);
+        assert!(inputs.contains_key("a_position"));
+        assert!(inputs.contains_key("a_texcoord"));
+
+        assert_eq!(vertex.blocks.get("Frame"), Some(&StorageQualifier::Uniform));
+        let mut uniforms = vertex.with(StorageQualifier::Uniform);
+        uniforms.extend(fragment.with(StorageQualifier::Uniform));
+        for name in UNIFORMS {
+            assert!(uniforms.contains_key(name), "Missing uniform {}", name);
+        }
+
+        assert_eq!(vertex.with(StorageQualifier::Out), fragment.with(StorageQualifier::In));
+    }
+
+    #[test]
+    fn info_log_line_numbers() {
+        assert_eq!(error_lines("ERROR: 0:12: 'x' : undeclared identifier\nERROR: 0:3: ''"), vec![12, 3]);
+        assert_eq!(error_lines("0:7(5): error: syntax error"), vec![7]);
+        assert_eq!(error_lines("WARNING: vec4 unused"), Vec::<usize>::new());
+    }
+}
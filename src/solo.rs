@@ -0,0 +1,306 @@
+use std::f32::consts::FRAC_PI_2;
+
+use crate::{ai, bounce, hit_paddle, hit_wall, multiball, paddle_shape, play_audio, reflect_off_side, Ball, Bounce,
+            Models, Paddle, Pong, RenderContext, Side, Vec2};
+use crate::config::{GameConfig, Mode};
+use crate::events::GameEvent;
+use crate::text::TextRenderer;
+
+// The launcher is drawn as a short paddle pointing where it aims
+const LAUNCHER_SIZE: Vec2 = Vec2 { x: 1.0, y: 0.5 };
+// Milliseconds the practice summary stays up before the next session
+const SUMMARY_TIME: f32 = 5000.0;
+// Closed sides are drawn as bars this many times thinner than a paddle
+const WALL_THINNING: f32 = 5.0;
+const TEXT_SIZE: f32 = 0.06;
+const LINE_HEIGHT: f32 = 0.1;
+
+// Single player modes for the left paddle. In squash the right side is a wall
+// to play against. In practice a launcher on the right feeds balls at the
+// player and counts how many come back.
+#[derive(Default)]
+pub struct Solo {
+    launched: u32,
+    timer: f32,
+    hits: u32,
+    misses: u32,
+    // Time left showing the results of a finished practice session
+    summary: Option<f32>
+}
+
+impl Solo {
+    pub fn reset(&mut self) {
+        *self = Solo::default();
+    }
+
+    // Whether the launcher fires a ball this frame of a practice session
+    fn launch_due(&mut self, delta: f32, config: &GameConfig) -> bool {
+        if self.summary.is_some() || self.launched >= config.launcher.balls {
+            return false;
+        }
+        self.timer += delta;
+        if self.timer < config.launcher.interval {
+            return false;
+        }
+        self.timer = 0.0;
+        self.launched += 1;
+        true
+    }
+
+    // Returns a ball touching the player's paddle, counting the hit
+    fn play(&mut self, paddle: &Paddle, ball: &mut Ball, config: &GameConfig) -> Option<Bounce> {
+        let impact = paddle.contact(ball).and_then(|contact| bounce(ball, contact, 1.0))?;
+        ball.curve = 0.0;
+        paddle.strike(ball, &impact, config);
+        ball.last_hitter = Some(Side::Left);
+        self.hits += 1;
+        Some(impact)
+    }
+
+    // Takes the balls that left the field out of play, counting those past
+    // the player as misses. Returned balls leave past the launcher.
+    fn take_misses(&mut self, balls: &mut Vec<Ball>, config: &GameConfig) -> u32 {
+        let mut missed = 0;
+        balls.retain(|ball| {
+            if ball.position.x < -config.goal_line {
+                missed += 1;
+            }
+            ball.position.x.abs() <= config.goal_line
+        });
+        self.misses += missed;
+        missed
+    }
+
+    // Ends a practice session once every ball has been launched and played,
+    // returning the hits and misses, and starts the next session after the
+    // summary has been up for `SUMMARY_TIME`
+    fn finish(&mut self, delta: f32, in_play: bool, config: &GameConfig) -> Option<(u32, u32)> {
+        if let Some(summary) = &mut self.summary {
+            *summary -= delta;
+            if *summary <= 0.0 {
+                self.reset();
+            }
+            None
+        } else if self.launched >= config.launcher.balls && !in_play {
+            self.summary = Some(SUMMARY_TIME);
+            Some((self.hits, self.misses))
+        } else {
+            None
+        }
+    }
+
+    // Draws the right wall or the launcher, and the results of a finished
+    // practice session
+    pub fn render(&self, models: &Models, ctx: &RenderContext, config: &GameConfig) {
+        models.paddle.pre_render(ctx);
+        if config.mode == Mode::Squash {
+            let size = Vec2::new(1.0 / WALL_THINNING, config.wall / 0.2);
+            models.paddle.render_scaled(&Vec2::new(config.wall, 0.0), &size, ctx);
+            return;
+        }
+        let velocity = launch_velocity(config);
+        let angle = velocity.y.atan2(velocity.x) - FRAC_PI_2;
+        models.paddle.render_rotated(&Vec2::new(config.paddle_x, 0.0), &LAUNCHER_SIZE, angle, ctx);
+
+        if self.summary.is_some() {
+            let total = (self.hits + self.misses).max(1);
+            let lines = [
+                format!("HITS {}", self.hits),
+                format!("MISSES {}", self.misses),
+                format!("ACCURACY {}%", self.hits * 100 / total)
+            ];
+            for (i, line) in lines.iter().enumerate() {
+                let x = -TextRenderer::width(line, TEXT_SIZE) / 2.0;
+                let y = LINE_HEIGHT * (1.0 - i as f32) + TEXT_SIZE / 2.0;
+                models.text.render(ctx, line, x, y, TEXT_SIZE, 0.9);
+            }
+        }
+    }
+}
+
+fn launch_velocity(config: &GameConfig) -> Vec2 {
+    let angle = config.launcher.angle.to_radians();
+    // A straight serve moves at (1, 1)
+    let speed = config.launcher.speed * std::f32::consts::SQRT_2;
+    Vec2::new(-speed * angle.cos(), speed * angle.sin())
+}
+
+// A ball leaving the launcher with its spin
+fn launch(config: &GameConfig) -> Ball {
+    let mut ball = Ball::new(Vec2::new(config.paddle_x, 0.0), launch_velocity(config), config.ball_tail_particles);
+    ball.curve = config.launcher.spin;
+    ball
+}
+
+// Advances a squash or practice game by `delta` milliseconds of game time
+pub fn update(pong: &mut Pong, delta: f32, timestamp: i32) {
+    let config = &pong.config;
+    let solo = &mut pong.solo;
+    let squash = config.mode == Mode::Squash;
+    let paddle = &mut pong.left;
+
+    if let Some(difficulty) = paddle.ai {
+        ai::control(paddle, difficulty, &pong.balls, config);
+    }
+    let direction = paddle.direction();
+    paddle.steer(direction, config.paddle_speed, delta, config.paddle_limit);
+    paddle.set_shape(paddle_shape(config));
+
+    if !squash && solo.launch_due(delta, config) {
+        let ball = launch(config);
+        pong.events.push(GameEvent::Serve { dx: ball.velocity.x, dy: ball.velocity.y });
+        pong.balls.push(ball);
+        play_audio(&pong.audio, &pong.bloop);
+    }
+
+    let mut paddle_hits = Vec::new();
+    let mut wall_bounces = Vec::new();
+    for ball in pong.balls.iter_mut() {
+        ball.advance(delta, config.ball_speed, config);
+
+        if let Some(impact) = solo.play(paddle, ball, config) {
+            paddle_hits.push(impact);
+        }
+
+        let walls = [Side::Top, Side::Bottom, Side::Right];
+        for wall in walls.iter().copied().filter(|wall| squash || *wall != Side::Right) {
+            if let Some(bounce) = reflect_off_side(ball, wall, config) {
+                wall_bounces.push((wall, bounce));
+            }
+        }
+
+        ball.tail.add(ball.position.clone(), Vec2::zero(), Vec2::zero(), 1000.0);
+        ball.tail.update(delta);
+    }
+
+    for impact in paddle_hits {
+        hit_paddle(pong, Side::Left, &impact);
+    }
+    for (wall, bounce) in wall_bounces {
+        hit_wall(pong, wall, &bounce);
+    }
+    let paddles = pong.side_paddles();
+    let config = &pong.config;
+    let solo = &mut pong.solo;
+
+    pong.recorder.create_sparks(&mut pong.sparks, config.sparks_per_hit);
+    pong.sparks.update(delta);
+    pong.recorder.record(delta, &pong.balls, paddles);
+
+    let missed = solo.take_misses(&mut pong.balls, config);
+    if missed > 0 && (squash || pong.balls.is_empty()) {
+        pong.events.push(GameEvent::RallyEnded { length: pong.rally });
+        pong.rally = 0;
+    }
+
+    if squash && pong.balls.is_empty() {
+        // Serves come off the wall
+        let velocity = multiball::serve_direction(timestamp);
+        let velocity = Vec2::new(-velocity.x.abs(), velocity.y);
+        pong.events.push(GameEvent::Serve { dx: velocity.x, dy: velocity.y });
+        pong.balls.push(Ball::new(Vec2::new(config.wall / 2.0, 0.0), velocity, config.ball_tail_particles));
+        play_audio(&pong.audio, &pong.bloop);
+    }
+
+    if !squash {
+        if let Some((hits, misses)) = solo.finish(delta, !pong.balls.is_empty(), config) {
+            pong.events.push(GameEvent::PracticeOver { hits, misses });
+        }
+    }
+    pong.scoreboard.set(solo.hits, solo.misses);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::SQRT_2;
+
+    use super::*;
+    use crate::{Track, PADDLE_SHAPE};
+
+    fn practice(balls: u32) -> GameConfig {
+        let mut config = GameConfig { mode: Mode::Practice, ..GameConfig::default() };
+        config.launcher.balls = balls;
+        config
+    }
+
+    fn ball(x: f32, dx: f32) -> Ball {
+        Ball::new(Vec2::new(x, 0.0), Vec2::new(dx, 0.0), 0)
+    }
+
+    #[test]
+    fn the_launcher_aims_at_the_player_with_its_speed_and_spin() {
+        let mut config = practice(1);
+        config.launcher.speed = 2.0;
+        config.launcher.spin = 0.001;
+        let ball = launch(&config);
+        assert_eq!((ball.position.x, ball.position.y), (config.paddle_x, 0.0));
+        assert!((ball.velocity.x + 2.0 * SQRT_2).abs() < 1e-6 && ball.velocity.y.abs() < 1e-6);
+        assert_eq!(ball.curve, 0.001);
+
+        config.launcher.angle = 30.0;
+        let velocity = launch_velocity(&config);
+        assert!((velocity.y.atan2(-velocity.x).to_degrees() - 30.0).abs() < 1e-3);
+        assert!((velocity.length() - 2.0 * SQRT_2).abs() < 1e-5);
+
+        // The steepest launch still heads for the player, steeper ones are
+        // rejected
+        config.launcher.angle = -75.0;
+        assert!(config.validate().is_ok());
+        let velocity = launch_velocity(&config);
+        assert!(velocity.x < 0.0 && velocity.y < 0.0);
+        config.launcher.angle = -76.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn the_launcher_fires_every_interval_until_the_session_is_out_of_balls() {
+        let config = practice(2);
+        let mut solo = Solo::default();
+        assert!(!solo.launch_due(config.launcher.interval - 1.0, &config));
+        assert!(solo.launch_due(1.0, &config));
+        assert!(!solo.launch_due(config.launcher.interval / 2.0, &config));
+        assert!(solo.launch_due(config.launcher.interval / 2.0, &config));
+        assert!(!solo.launch_due(config.launcher.interval * 2.0, &config));
+        assert_eq!(solo.launched, 2);
+    }
+
+    #[test]
+    fn returns_are_hits_and_balls_past_the_player_misses() {
+        let config = practice(3);
+        let paddle = Paddle::new(Vec2::new(-config.paddle_x, 0.0), Track::Vertical, PADDLE_SHAPE);
+        let mut solo = Solo::default();
+
+        let mut returned = ball(-0.82, -1.0);
+        returned.curve = 0.001;
+        assert!(solo.play(&paddle, &mut returned, &config).is_some());
+        assert!(returned.velocity.x > 0.0 && returned.curve == 0.0);
+        assert!(returned.last_hitter == Some(Side::Left));
+        assert!(solo.play(&paddle, &mut returned, &config).is_none());
+        assert_eq!(solo.hits, 1);
+
+        // Returned balls leave past the launcher without counting
+        let mut balls = vec![ball(-1.1, -1.0), ball(1.1, 1.0), ball(0.0, 1.0)];
+        assert_eq!(solo.take_misses(&mut balls, &config), 1);
+        assert_eq!(balls.len(), 1);
+        assert_eq!((solo.hits, solo.misses), (1, 1));
+    }
+
+    #[test]
+    fn sessions_end_with_a_summary_before_starting_over() {
+        let config = practice(2);
+        let mut solo = Solo { launched: 1, hits: 1, ..Solo::default() };
+        assert_eq!(solo.finish(16.0, false, &config), None);
+
+        solo.launched = 2;
+        assert_eq!(solo.finish(16.0, true, &config), None);
+        assert_eq!(solo.finish(16.0, false, &config), Some((1, 0)));
+        assert!(!solo.launch_due(config.launcher.interval, &config));
+
+        // The summary stays up before the next session starts from scratch
+        assert_eq!(solo.finish(SUMMARY_TIME - 1.0, false, &config), None);
+        assert_eq!((solo.launched, solo.hits), (2, 1));
+        assert_eq!(solo.finish(1.0, false, &config), None);
+        assert_eq!((solo.launched, solo.hits, solo.summary), (0, 0, None));
+        assert!(solo.launch_due(config.launcher.interval, &config));
+    }
+}
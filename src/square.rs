@@ -0,0 +1,307 @@
+use crate::{ai, bounce, hit_paddle, hit_wall, multiball, paddle_shape, play_audio, reflect_off_side, Ball, Bounce,
+            Models, Paddle, Pong, RenderContext, Side, Track, Vec2};
+use crate::collision;
+use crate::config::GameConfig;
+use crate::events::GameEvent;
+use crate::text::TextRenderer;
+
+const SIDES: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+// The paddle model is 0.1 by 0.4, turned on its side for horizontal tracks
+const HORIZONTAL_PADDLE_SIZE: Vec2 = Vec2 { x: 4.0, y: 0.25 };
+// Closed sides are drawn as bars this many times thinner than a paddle
+const WALL_THINNING: f32 = 5.0;
+const TEXT_SIZE: f32 = 0.05;
+
+// Four-player mode: paddles on every side of the field, each guarding its own
+// goal. Players lose a life for every goal and their side is walled off when
+// they run out.
+pub struct FourPlayer {
+    pub top: Paddle,
+    pub bottom: Paddle,
+    lives: [u32; 4]
+}
+
+impl FourPlayer {
+    pub fn new(config: &GameConfig) -> FourPlayer {
+        let shape = paddle_shape(config);
+        FourPlayer {
+            top: Paddle::new(Vec2::new(0.0, config.paddle_x), Track::Horizontal, shape),
+            bottom: Paddle::new(Vec2::new(0.0, -config.paddle_x), Track::Horizontal, shape),
+            lives: [config.lives; 4]
+        }
+    }
+
+    pub fn reset(&mut self, config: &GameConfig) {
+        self.lives = [config.lives; 4];
+    }
+
+    // Brings the top and bottom paddles up to date after configuration changes
+    pub fn apply_config(&mut self, config: &GameConfig) {
+        self.top.position.y = config.paddle_x;
+        self.bottom.position.y = -config.paddle_x;
+        for paddle in [&mut self.top, &mut self.bottom] {
+            paddle.set_shape(paddle_shape(config));
+            paddle.slide(0.0, config.paddle_limit);
+        }
+    }
+
+    pub fn lives(&self, side: Side) -> u32 {
+        self.lives[index(side)]
+    }
+
+    pub fn alive(&self, side: Side) -> bool {
+        self.lives(side) > 0
+    }
+
+    // Takes a life from the player on `side`, returning the lives left
+    fn lose_life(&mut self, side: Side) -> u32 {
+        let lives = &mut self.lives[index(side)];
+        *lives = lives.saturating_sub(1);
+        *lives
+    }
+
+    // The match is over once at most one player is left
+    fn over(&self) -> bool {
+        SIDES.iter().filter(|side| self.alive(**side)).count() <= 1
+    }
+
+    // The last player standing. Players knocked out together by the same goals
+    // share last place, so nobody wins when no one is left.
+    fn winner(&self) -> Option<Side> {
+        let mut remaining = SIDES.iter().copied().filter(|side| self.alive(*side));
+        match (remaining.next(), remaining.next()) {
+            (Some(side), None) => Some(side),
+            _ => None
+        }
+    }
+
+    // Draws the top and bottom paddles, walls for eliminated players and
+    // everyone's remaining lives
+    pub fn render(&self, models: &Models, ctx: &RenderContext, config: &GameConfig) {
+        models.paddle.pre_render(ctx);
+        for (side, paddle) in [(Side::Top, &self.top), (Side::Bottom, &self.bottom)] {
+            if self.alive(side) {
+                models.paddle.render_scaled(&paddle.position, &HORIZONTAL_PADDLE_SIZE, ctx);
+            }
+        }
+        for side in SIDES {
+            if self.alive(side) {
+                continue;
+            }
+            let outward = side.outward();
+            let position = Vec2::new(outward.x * config.wall, outward.y * config.wall);
+            // Stretches a paddle along the whole side
+            let length = config.wall / 0.2;
+            let size = match side {
+                Side::Left | Side::Right => Vec2::new(1.0 / WALL_THINNING, length),
+                Side::Top | Side::Bottom => Vec2::new(length * 4.0, HORIZONTAL_PADDLE_SIZE.y / WALL_THINNING)
+            };
+            models.paddle.render_scaled(&position, &size, ctx);
+        }
+
+        for side in SIDES {
+            let text = self.lives(side).to_string();
+            let outward = side.outward();
+            let x = outward.x * 0.97 - TextRenderer::width(&text, TEXT_SIZE) / 2.0;
+            let y = outward.y * 0.97 + TEXT_SIZE / 2.0;
+            models.text.render(ctx, &text, x, y, TEXT_SIZE, 0.6);
+        }
+    }
+}
+
+fn index(side: Side) -> usize {
+    match side {
+        Side::Left => 0,
+        Side::Right => 1,
+        Side::Top => 2,
+        Side::Bottom => 3
+    }
+}
+
+// Bounces a ball off the sides of players without lives, which are walls
+fn bounce_off_closed_sides(ball: &mut Ball, alive: &[bool; 4], config: &GameConfig) -> Vec<(Side, Bounce)> {
+    SIDES.iter().copied()
+        .filter(|side| !alive[index(*side)])
+        .filter_map(|side| reflect_off_side(ball, side, config).map(|bounce| (side, bounce)))
+        .collect()
+}
+
+// The player whose goal a ball has left the field through, if they are still
+// in the game. Balls leaving across a corner count against the side they are
+// furthest out of.
+fn conceding(ball: &Ball, alive: &[bool; 4], config: &GameConfig) -> Option<Side> {
+    SIDES.iter().copied()
+        .filter(|side| alive[index(*side)] && ball.position.dot(&side.outward()) > config.goal_line)
+        .max_by(|a, b| ball.position.dot(&a.outward()).total_cmp(&ball.position.dot(&b.outward())))
+}
+
+// Advances a four-player match by `delta` milliseconds of game time
+pub fn update(pong: &mut Pong, delta: f32, timestamp: i32) {
+    let config = &pong.config;
+    let square = &mut pong.square;
+    let alive = SIDES.map(|side| square.lives[index(side)] > 0);
+    let mut paddles = [(Side::Left, &mut pong.left), (Side::Right, &mut pong.right),
+                       (Side::Top, &mut square.top), (Side::Bottom, &mut square.bottom)];
+
+    for (side, paddle) in paddles.iter_mut() {
+        if !alive[index(*side)] {
+            continue;
+        }
+        if let Some(difficulty) = paddle.ai {
+            ai::control(paddle, difficulty, &pong.balls, config);
+        }
+        let direction = paddle.direction();
+        paddle.steer(direction, config.paddle_speed, delta, config.paddle_limit);
+        paddle.set_shape(paddle_shape(config));
+    }
+
+    let mut paddle_hits = Vec::new();
+    let mut wall_bounces = Vec::new();
+    for ball in pong.balls.iter_mut() {
+        ball.advance(delta, config.ball_speed, config);
+
+        for (side, paddle) in paddles.iter() {
+            if !alive[index(*side)] {
+                continue;
+            }
+            let contact = match collision::collide(&ball.position, &ball.shape, &paddle.position, &paddle.shape) {
+                Some(contact) => contact,
+                None => continue
+            };
+            let impact = match bounce(ball, contact, 1.0) {
+                Some(impact) => impact,
+                None => continue
+            };
+            paddle.strike(ball, &impact, config);
+            ball.last_hitter = Some(*side);
+            paddle_hits.push((*side, impact));
+        }
+
+        wall_bounces.extend(bounce_off_closed_sides(ball, &alive, config));
+
+        ball.tail.add(ball.position.clone(), Vec2::zero(), Vec2::zero(), 1000.0);
+        ball.tail.update(delta);
+    }
+
+    for (side, impact) in paddle_hits {
+        hit_paddle(pong, side, &impact);
+    }
+    for (side, bounce) in wall_bounces {
+        hit_wall(pong, side, &bounce);
+    }
+    let config = &pong.config;
+
+    if multiball::collide_balls(&mut pong.balls, &mut pong.recorder) {
+        play_audio(&pong.audio, &pong.boop);
+    }
+
+    pong.recorder.create_sparks(&mut pong.sparks, config.sparks_per_hit);
+    pong.sparks.update(delta);
+    let paddles = pong.side_paddles();
+    pong.recorder.record(delta, &pong.balls, paddles);
+
+    // A ball leaving the field costs the player on that side a life
+    let mut conceded = Vec::new();
+    pong.balls.retain(|ball: &Ball| {
+        match conceding(ball, &alive, config) {
+            Some(side) => {
+                conceded.push(side);
+                false
+            },
+            None => true
+        }
+    });
+    for side in &conceded {
+        let lives = pong.square.lose_life(*side);
+        pong.events.push(GameEvent::LifeLost { side: *side, lives });
+    }
+    if conceded.is_empty() {
+        return;
+    }
+
+    if pong.square.over() {
+        pong.events.push(GameEvent::LastStanding { winner: pong.square.winner() });
+        pong.square.reset(&pong.config);
+        pong.balls.clear();
+        if let Some(audio) = &mut pong.audio {
+            audio.music.start(&audio.ctx);
+        }
+    }
+
+    if pong.balls.is_empty() {
+        pong.events.push(GameEvent::RallyEnded { length: pong.rally });
+        pong.rally = 0;
+        let velocity = multiball::serve_direction(timestamp);
+        pong.events.push(GameEvent::Serve { dx: velocity.x, dy: velocity.y });
+        pong.balls.push(Ball::new(Vec2::zero(), velocity, pong.config.ball_tail_particles));
+        play_audio(&pong.audio, &pong.bloop);
+    }
+    pong.scoreboard.set(pong.square.lives(Side::Left), pong.square.lives(Side::Right));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(lives: [u32; 4]) -> FourPlayer {
+        let mut square = FourPlayer::new(&GameConfig::default());
+        square.lives = lives;
+        square
+    }
+
+    fn ball(x: f32, y: f32) -> Ball {
+        Ball::new(Vec2::new(x, y), Vec2::zero(), 0)
+    }
+
+    #[test]
+    fn goals_cost_the_player_on_that_side_a_life() {
+        let config = GameConfig::default();
+        let alive = [true; 4];
+        assert!(conceding(&ball(0.5, 0.5), &alive, &config).is_none());
+        assert!(conceding(&ball(-1.1, 0.2), &alive, &config) == Some(Side::Left));
+        assert!(conceding(&ball(0.2, 1.1), &alive, &config) == Some(Side::Top));
+        assert!(conceding(&ball(1.1, -1.2), &alive, &config) == Some(Side::Bottom));
+        // Walled off sides have no goal left
+        assert!(conceding(&ball(-1.1, 0.2), &[false, true, true, true], &config).is_none());
+
+        let mut square = square([2, 5, 5, 5]);
+        assert_eq!(square.lose_life(Side::Left), 1);
+        assert_eq!(square.lose_life(Side::Left), 0);
+        assert_eq!(square.lose_life(Side::Left), 0);
+        assert!(!square.alive(Side::Left));
+        assert_eq!(square.lives(Side::Right), 5);
+    }
+
+    #[test]
+    fn sides_of_eliminated_players_are_walled_off() {
+        let config = GameConfig::default();
+        let mut ball = Ball::new(Vec2::new(-0.96, 0.0), Vec2::new(-1.0, 0.5), 0);
+        assert!(bounce_off_closed_sides(&mut ball, &[true; 4], &config).is_empty());
+        assert_eq!(ball.velocity.x, -1.0);
+
+        let alive = [false, true, true, true];
+        let bounces = bounce_off_closed_sides(&mut ball, &alive, &config);
+        assert!(bounces.len() == 1 && bounces[0].0 == Side::Left);
+        assert_eq!((ball.velocity.x, ball.velocity.y), (1.0, 0.5));
+
+        // Balls already heading back in are left alone
+        assert!(bounce_off_closed_sides(&mut ball, &alive, &config).is_empty());
+    }
+
+    #[test]
+    fn the_last_player_standing_wins() {
+        assert!(!square([1, 1, 0, 0]).over());
+        assert!(square([1, 1, 0, 0]).winner().is_none());
+
+        let last = square([0, 0, 0, 2]);
+        assert!(last.over());
+        assert!(last.winner() == Some(Side::Bottom));
+
+        // The last two players going out together share last place
+        let mut square = square([0, 1, 1, 0]);
+        square.lose_life(Side::Right);
+        square.lose_life(Side::Top);
+        assert!(square.over());
+        assert!(square.winner().is_none());
+    }
+}
@@ -0,0 +1,84 @@
+use web_sys::WebGlTexture;
+
+use crate::{data, RenderContext, GL};
+use crate::error::PongError;
+use crate::gl::VertexArray;
+
+// Glyphs are laid out in a 16x4 grid of 8x8 cells in a 128x32 atlas texture
+const ATLAS_COLUMNS: usize = 16;
+const ATLAS_WIDTH: usize = 128;
+const ATLAS_HEIGHT: usize = 32;
+const CELL_SIZE: usize = 8;
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+#[derive(Clone)]
+pub struct TextRenderer {
+    texture: WebGlTexture,
+    vertices: VertexArray
+}
+
+impl TextRenderer {
+    pub fn new(ctx: &RenderContext) -> Result<TextRenderer, PongError> {
+        let mut pixels = vec![0u8; ATLAS_WIDTH * ATLAS_HEIGHT * 4];
+        for (i, glyph) in data::FONT.iter().enumerate() {
+            let cell_x = (i % ATLAS_COLUMNS) * CELL_SIZE;
+            let cell_y = (i / ATLAS_COLUMNS) * CELL_SIZE;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        let offset = ((cell_y + row) * ATLAS_WIDTH + cell_x + column) * 4;
+                        pixels[offset..offset + 4].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+                    }
+                }
+            }
+        }
+
+        let texture = ctx.load_texture(&pixels, ATLAS_WIDTH as i32, ATLAS_HEIGHT as i32)?;
+        let vertices = VertexArray::new(ctx)?;
+
+        Ok(TextRenderer { texture, vertices })
+    }
+
+    // Width of `text` rendered with glyphs `size` units high
+    pub fn width(text: &str, size: f32) -> f32 {
+        let advance = size * (GLYPH_WIDTH + 1) as f32 / GLYPH_HEIGHT as f32;
+        text.chars().count() as f32 * advance
+    }
+
+    // Renders a line of text with its top left corner at (x, y). Lowercase
+    // letters are shown in uppercase and unsupported characters as '?'.
+    pub fn render(&self, ctx: &RenderContext, text: &str, x: f32, y: f32, size: f32, opacity: f32) {
+        let pixel = size / GLYPH_HEIGHT as f32;
+        let width = GLYPH_WIDTH as f32 * pixel;
+        let advance = (GLYPH_WIDTH + 1) as f32 * pixel;
+
+        let mut vertices: Vec<f32> = Vec::with_capacity(text.len() * 6 * 4);
+        for (i, c) in text.chars().enumerate() {
+            let c = c.to_ascii_uppercase();
+            let index = if (' '..='_').contains(&c) { c as usize - 32 } else { '?' as usize - 32 };
+            let u0 = ((index % ATLAS_COLUMNS) * CELL_SIZE) as f32 / ATLAS_WIDTH as f32;
+            let u1 = u0 + GLYPH_WIDTH as f32 / ATLAS_WIDTH as f32;
+            let v0 = ((index / ATLAS_COLUMNS) * CELL_SIZE) as f32 / ATLAS_HEIGHT as f32;
+            let v1 = v0 + GLYPH_HEIGHT as f32 / ATLAS_HEIGHT as f32;
+
+            let x0 = x + i as f32 * advance;
+            let x1 = x0 + width;
+            let y0 = y;
+            let y1 = y - size;
+            vertices.extend_from_slice(&[
+                x0, y1, u0, v1,  x1, y0, u1, v0,  x0, y0, u0, v0,
+                x0, y1, u0, v1,  x1, y1, u1, v1,  x1, y0, u1, v0
+            ]);
+        }
+
+        self.vertices.upload(ctx, &vertices, GL::DYNAMIC_DRAW);
+        self.vertices.bind(ctx);
+        ctx.gl.active_texture(GL::TEXTURE0);
+        ctx.gl.bind_texture(GL::TEXTURE_2D, Some(&self.texture));
+        ctx.gl.uniform1i(Some(&ctx.sampler), 0);
+        ctx.gl.uniform1f(Some(&ctx.opacity), opacity);
+        ctx.gl.uniform4f(Some(&ctx.offset), 0.0, 0.0, 0.0, 0.0);
+        ctx.draw(vertices.len() as i32 / 4);
+    }
+}